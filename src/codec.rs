@@ -0,0 +1,275 @@
+use crate::proto;
+use crate::types::{GenerateRequest, GenerateResponse};
+use anyhow::{bail, Context, Result};
+use std::str::FromStr;
+
+/// Wire format used to exchange `GenerateRequest`/`GenerateResponse` with sqlc.
+///
+/// Process plugins receive protobuf by default and JSON when the plugin is
+/// configured with `format: json`. The response is always written back in the
+/// same format as the request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Protobuf,
+}
+
+impl Format {
+    /// Guesses the format from the raw request bytes.
+    ///
+    /// sqlc's JSON requests start with `{`, which can never be the first byte
+    /// of a protobuf-encoded `GenerateRequest` (it would be field 15 with the
+    /// deprecated group wire type). Only the first byte is checked: later
+    /// bytes of a protobuf request can be anything, e.g. a length of 123 right
+    /// after the `settings` tag `0x0A` reads as `\n{`.
+    pub fn detect(input: &[u8]) -> Self {
+        match input.first() {
+            Some(b'{') => Format::Json,
+            _ => Format::Protobuf,
+        }
+    }
+
+    pub fn decode_request(self, input: &[u8]) -> Result<GenerateRequest> {
+        match self {
+            Format::Json => {
+                serde_json::from_slice(input).context("Failed to parse GenerateRequest from JSON")
+            }
+            Format::Protobuf => proto::decode_request(input),
+        }
+    }
+
+    pub fn encode_response(self, response: GenerateResponse) -> Result<Vec<u8>> {
+        match self {
+            Format::Json => serde_json::to_vec(&response)
+                .context("Failed to serialize GenerateResponse to JSON"),
+            Format::Protobuf => Ok(proto::encode_response(response)),
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "json" => Ok(Format::Json),
+            "protobuf" | "proto" => Ok(Format::Protobuf),
+            other => bail!("Unknown format '{other}', expected 'json' or 'protobuf'"),
+        }
+    }
+}
+
+/// Decodes a request, runs the generator and encodes the response.
+///
+/// When `format` is `None` the format is detected from the input.
+pub fn run(input: &[u8], format: Option<Format>) -> Result<Vec<u8>> {
+    let format = format.unwrap_or_else(|| Format::detect(input));
+    let request = format.decode_request(input)?;
+    let response = crate::generate_code(request)?;
+    format.encode_response(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_json() {
+        assert_eq!(Format::detect(b"{\"settings\": {}}"), Format::Json);
+    }
+
+    #[test]
+    fn detects_protobuf() {
+        assert_eq!(Format::detect(&[0x0A, 0x02, 0x08, 0x01]), Format::Protobuf);
+        assert_eq!(Format::detect(b""), Format::Protobuf);
+    }
+
+    #[test]
+    fn detects_protobuf_with_settings_length_of_open_brace() {
+        // `settings` (field 1, length-delimited) with a length of 123
+        let mut input = vec![0x0A, b'{'];
+        input.extend([0; 123]);
+        assert_eq!(Format::detect(&input), Format::Protobuf);
+    }
+
+    #[test]
+    fn decodes_protobuf_with_settings_length_of_open_brace() {
+        let request = proto::GenerateRequest {
+            settings: Some(proto::Settings {
+                version: "2".to_string(),
+                engine: "x".repeat(118),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let input = prost::Message::encode_to_vec(&request);
+        assert_eq!(&input[..2], &[0x0A, b'{']);
+
+        let format = Format::detect(&input);
+        assert_eq!(format, Format::Protobuf);
+        assert!(format.decode_request(&input).is_ok());
+    }
+
+    /// A request in the protojson shape sqlc sends with `format: json`: proto
+    /// field names, every field present, base64 `bytes` and a single
+    /// `codegen` object. The built-in `pg_catalog` and `information_schema`
+    /// schemas are left out of the catalog.
+    const PROTOJSON_REQUEST: &[u8] = include_bytes!("../tests/corpus/protojson/authors.json");
+
+    fn response_files(output: &[u8]) -> Vec<(String, String)> {
+        use base64::Engine;
+
+        let response: serde_json::Value = serde_json::from_slice(output).unwrap();
+        response["files"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|file| {
+                let contents = base64::engine::general_purpose::STANDARD
+                    .decode(file["contents"].as_str().unwrap())
+                    .unwrap();
+                (
+                    file["name"].as_str().unwrap().to_string(),
+                    String::from_utf8(contents).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn decodes_protojson_request() {
+        let request = Format::Json.decode_request(PROTOJSON_REQUEST).unwrap();
+        assert_eq!(
+            request.plugin_options,
+            serde_json::json!({ "emit_interface": true })
+        );
+        let codegen = request.settings.codegen.unwrap();
+        assert_eq!(
+            codegen.options,
+            Some(serde_json::json!({ "emit_interface": true }))
+        );
+        assert_eq!(request.global_options, None);
+        assert_eq!(request.catalog.comment, None);
+        assert_eq!(request.queries[0].columns[0].length, None);
+    }
+
+    #[test]
+    fn answers_protojson_with_base64_contents() {
+        let output = run(PROTOJSON_REQUEST, None).unwrap();
+        let files = response_files(&output);
+        let names: Vec<_> = files.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["models.rs", "queries.rs", "querier.rs", "lib.rs"]);
+
+        let (_, queries) = &files[1];
+        assert!(queries
+            .contains("pub async fn get_author(&self, id: i64) -> Result<Authors, SqlxError>"));
+        let (_, models) = &files[0];
+        assert!(!models.contains("///"));
+    }
+
+    /// A `places` table with an `address` composite and an `email` domain
+    /// column, as stock sqlc reports them: the composite by name only and
+    /// the domain only as the column's type name.
+    fn places_request(plugin_options: serde_json::Value) -> proto::GenerateRequest {
+        let type_name = |name: &str| proto::Identifier {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        let column = |name: &str, sql_type: &str| proto::Column {
+            name: name.to_string(),
+            not_null: true,
+            r#type: Some(type_name(sql_type)),
+            ..Default::default()
+        };
+
+        proto::GenerateRequest {
+            settings: Some(proto::Settings {
+                version: "2".to_string(),
+                engine: "postgresql".to_string(),
+                ..Default::default()
+            }),
+            catalog: Some(proto::Catalog {
+                default_schema: "public".to_string(),
+                schemas: vec![proto::Schema {
+                    name: "public".to_string(),
+                    tables: vec![proto::Table {
+                        rel: Some(type_name("places")),
+                        columns: vec![column("home", "address"), column("owner", "email")],
+                        ..Default::default()
+                    }],
+                    composite_types: vec![proto::CompositeType {
+                        name: "address".to_string(),
+                        comment: "A postal address".to_string(),
+                    }],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+            sqlc_version: "v1.27.0".to_string(),
+            plugin_options: plugin_options.to_string().into_bytes(),
+            ..Default::default()
+        }
+    }
+
+    fn protobuf_models(request: &proto::GenerateRequest) -> String {
+        let output = run(&prost::Message::encode_to_vec(request), None).unwrap();
+        let response: proto::GenerateResponse = prost::Message::decode(&*output).unwrap();
+        let models = response
+            .files
+            .into_iter()
+            .find(|file| file.name == "models.rs")
+            .unwrap();
+        String::from_utf8(models.contents).unwrap()
+    }
+
+    #[test]
+    fn defines_composites_and_domains_from_options_for_protobuf_requests() {
+        let models = protobuf_models(&places_request(serde_json::json!({
+            "composite_types": [{
+                "name": "address",
+                "attributes": [
+                    { "name": "street", "db_type": "text" },
+                    { "name": "lines", "db_type": "text", "is_array": true }
+                ]
+            }],
+            "domains": [{ "name": "email", "base_type": "pg_catalog.text" }]
+        })));
+
+        assert!(models.contains(
+            "/// A postal address\n\
+             #[derive(Debug, Clone, Serialize, Deserialize, sqlx::Type)]\n\
+             #[sqlx(type_name = \"address\")]\n\
+             pub struct Address {\n"
+        ));
+        assert!(models.contains("    pub street: Option<String>,\n"));
+        assert!(models.contains("    pub lines: Option<Vec<String>>,\n"));
+        assert!(models.contains("    pub home: Address,\n"));
+        assert!(models.contains("    pub owner: String,\n"));
+    }
+
+    #[test]
+    fn protobuf_composites_and_domains_fall_back_to_overrides() {
+        let models = protobuf_models(&places_request(serde_json::json!({
+            "overrides": [
+                { "db_type": "address", "rust_type": "crate::Address" },
+                { "db_type": "email", "rust_type": "String" }
+            ]
+        })));
+
+        assert!(!models.contains("pub struct Address"));
+        assert!(models.contains("    pub home: crate::Address,\n"));
+        assert!(models.contains("    pub owner: String,\n"));
+    }
+
+    #[test]
+    fn rejects_composite_options_for_unknown_types() {
+        let request = places_request(serde_json::json!({
+            "composite_types": [{ "name": "other.address", "attributes": [] }]
+        }));
+        let err = run(&prost::Message::encode_to_vec(&request), None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "composite_types: other.address is not a composite type in the catalog"
+        );
+    }
+}
@@ -1,46 +1,483 @@
+use crate::ident;
+use crate::sql;
 use crate::type_mapping::TypeMapper;
-use crate::types::{Enum, File, GenerateRequest, GenerateResponse, PluginOptions, Query, Table};
-use anyhow::Result;
-use heck::{ToPascalCase, ToSnakeCase};
+use crate::types::{
+    Column, CompositeType, Domain, Enum, File, GenerateRequest, GenerateResponse, Identifier,
+    Parameter, PluginOptions, Query, Table,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use heck::ToSnakeCase;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+/// Helper emitted into the queries file when a `:copyfrom` query exists.
+const COPY_FIELD_ENCODER: &str = r#"const COPY_CHUNK_SIZE: usize = 64 * 1024;
+
+fn encode_copy_field<'q, T>(buf: &mut Vec<u8>, value: &T)
+where
+    T: sqlx::Encode<'q, Postgres>,
+{
+    let mut field = sqlx::postgres::PgArgumentBuffer::default();
+    match value.encode_by_ref(&mut field) {
+        sqlx::encode::IsNull::Yes => buf.extend_from_slice(&(-1i32).to_be_bytes()),
+        sqlx::encode::IsNull::No => {
+            buf.extend_from_slice(&(field.len() as i32).to_be_bytes());
+            buf.extend_from_slice(&field);
+        }
+    }
+}
+"#;
+
+/// Transaction support emitted into the queries file next to `Database`.
+const TRANSACTION_SUPPORT: &str = r#"/// Isolation level a transaction is started with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IsolationLevel {
+    ReadCommitted,
+    RepeatableRead,
+    Serializable,
+}
+
+impl IsolationLevel {
+    fn set_transaction(self) -> &'static str {
+        match self {
+            IsolationLevel::ReadCommitted => "SET TRANSACTION ISOLATION LEVEL READ COMMITTED",
+            IsolationLevel::RepeatableRead => "SET TRANSACTION ISOLATION LEVEL REPEATABLE READ",
+            IsolationLevel::Serializable => "SET TRANSACTION ISOLATION LEVEL SERIALIZABLE",
+        }
+    }
+}
+
+/// How `Database::transaction_with` runs its closure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransactionOptions {
+    /// Isolation level of the transaction, the server default when `None`.
+    pub isolation: Option<IsolationLevel>,
+    /// How many times the closure is retried after a serialization failure.
+    pub max_retries: u32,
+}
+
+impl Default for TransactionOptions {
+    fn default() -> Self {
+        Self {
+            isolation: None,
+            max_retries: 3,
+        }
+    }
+}
+
+/// A transaction started with `Database::begin`. Dropping it without calling
+/// `commit` rolls it back.
+pub struct DatabaseTx {
+    pub(crate) tx: sqlx::Transaction<'static, Postgres>,
+}
+
+impl DatabaseTx {
+    pub async fn commit(self) -> Result<(), SqlxError> {
+        self.tx.commit().await
+    }
+
+    pub async fn rollback(self) -> Result<(), SqlxError> {
+        self.tx.rollback().await
+    }
+}
+
+/// Whether an error is a serialization failure (SQLSTATE 40001), meaning the
+/// transaction can succeed when retried.
+fn is_serialization_failure(err: &SqlxError) -> bool {
+    match err {
+        SqlxError::Database(err) => err.code().as_deref() == Some("40001"),
+        _ => false,
+    }
+}
+"#;
+
+/// `begin` and `transaction` methods emitted into `impl Database`.
+const TRANSACTION_METHODS: &str = r#"    pub async fn begin(&self) -> Result<DatabaseTx, SqlxError> {
+        let tx = self.pool.begin().await?;
+        Ok(DatabaseTx { tx })
+    }
+
+    pub async fn begin_with(&self, isolation: IsolationLevel) -> Result<DatabaseTx, SqlxError> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(isolation.set_transaction())
+            .execute(&mut *tx)
+            .await?;
+        Ok(DatabaseTx { tx })
+    }
+
+    /// Runs `f` in a transaction that is committed when it returns `Ok` and
+    /// rolled back when it returns `Err`.
+    ///
+    /// ```ignore
+    /// let user = db.transaction(async |tx| {
+    ///     let user = tx.get_user(id).await?;
+    ///     tx.delete_user(user.id).await?;
+    ///     Ok(user)
+    /// }).await?;
+    /// ```
+    pub async fn transaction<T>(
+        &self,
+        f: impl AsyncFnMut(&mut DatabaseTx) -> Result<T, SqlxError>,
+    ) -> Result<T, SqlxError> {
+        self.transaction_with(TransactionOptions::default(), f).await
+    }
+
+    /// Like `transaction`, with the given isolation level. The whole
+    /// transaction is retried, calling `f` again, when it fails with a
+    /// serialization failure, at most `options.max_retries` times.
+    pub async fn transaction_with<T>(
+        &self,
+        options: TransactionOptions,
+        mut f: impl AsyncFnMut(&mut DatabaseTx) -> Result<T, SqlxError>,
+    ) -> Result<T, SqlxError> {
+        let mut retries = 0;
+        loop {
+            let mut tx = match options.isolation {
+                Some(isolation) => self.begin_with(isolation).await?,
+                None => self.begin().await?,
+            };
+            let result = match f(&mut tx).await {
+                Ok(value) => tx.commit().await.map(|()| value),
+                Err(err) => {
+                    // The original error is more useful than a failed rollback
+                    let _ = tx.rollback().await;
+                    Err(err)
+                }
+            };
+            match result {
+                Err(err) if retries < options.max_retries && is_serialization_failure(&err) => {
+                    retries += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+"#;
+
+/// Serde support for `PgRange` fields, which sqlx doesn't provide, emitted
+/// into the models file when a range type is used.
+const PG_RANGE_SERDE: &str = r#"/// Serde support for `PgRange` fields, also inside `Option` and `Vec`, as
+/// `{"start": .., "end": ..}` objects of `Bound`s.
+pub mod pg_range {
+    use serde::de::DeserializeOwned;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use sqlx::postgres::types::PgRange;
+    use std::ops::Bound;
+
+    #[derive(Serialize, Deserialize)]
+    pub struct Bounds<T> {
+        start: Bound<T>,
+        end: Bound<T>,
+    }
+
+    pub trait Ranges: Sized {
+        type Repr: Serialize + DeserializeOwned;
+
+        fn to_repr(&self) -> Self::Repr;
+
+        fn from_repr(repr: Self::Repr) -> Self;
+    }
+
+    impl<T: Clone + Serialize + DeserializeOwned> Ranges for PgRange<T> {
+        type Repr = Bounds<T>;
+
+        fn to_repr(&self) -> Bounds<T> {
+            Bounds {
+                start: self.start.clone(),
+                end: self.end.clone(),
+            }
+        }
+
+        fn from_repr(repr: Bounds<T>) -> Self {
+            PgRange {
+                start: repr.start,
+                end: repr.end,
+            }
+        }
+    }
+
+    impl<R: Ranges> Ranges for Option<R> {
+        type Repr = Option<R::Repr>;
+
+        fn to_repr(&self) -> Self::Repr {
+            self.as_ref().map(R::to_repr)
+        }
+
+        fn from_repr(repr: Self::Repr) -> Self {
+            repr.map(R::from_repr)
+        }
+    }
+
+    impl<R: Ranges> Ranges for Vec<R> {
+        type Repr = Vec<R::Repr>;
+
+        fn to_repr(&self) -> Self::Repr {
+            self.iter().map(R::to_repr).collect()
+        }
+
+        fn from_repr(repr: Self::Repr) -> Self {
+            repr.into_iter().map(R::from_repr).collect()
+        }
+    }
+
+    pub fn serialize<R: Ranges, S: Serializer>(value: &R, serializer: S) -> Result<S::Ok, S::Error> {
+        value.to_repr().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, R: Ranges, D: Deserializer<'de>>(deserializer: D) -> Result<R, D::Error> {
+        R::Repr::deserialize(deserializer).map(R::from_repr)
+    }
+}
+"#;
+
+/// Types the generated code defines or uses unqualified, which generated
+/// models would shadow.
+const RESERVED_TYPE_NAMES: &[&str] = &[
+    "Database",
+    "DatabaseTx",
+    "IsolationLevel",
+    "TransactionOptions",
+    "Querier",
+    "MockDatabase",
+    "MockCall",
+    "Option",
+    "Result",
+    "Vec",
+    "String",
+    "Box",
+    "Pool",
+    "Postgres",
+    "Row",
+    "FromRow",
+    "SqlxError",
+    "PgQueryResult",
+    "PgRow",
+    "Serialize",
+    "Deserialize",
+    "DateTime",
+    "NaiveDate",
+    "NaiveDateTime",
+    "NaiveTime",
+    "Utc",
+    "Uuid",
+    "Decimal",
+    "Mutex",
+    "VecDeque",
+];
+
+/// Methods the generated `Database`, `DatabaseTx` and `MockDatabase` define
+/// next to the query methods.
+const RESERVED_METHOD_NAMES: &[&str] = &[
+    "new",
+    "begin",
+    "begin_with",
+    "transaction",
+    "transaction_with",
+    "prepare_all",
+    "prepare_connection",
+    "commit",
+    "rollback",
+    "calls",
+];
+
+/// The type a query method is generated on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Receiver {
+    /// `Database`, running on its pool or on the `db` argument.
+    Database,
+    /// `DatabaseTx`, running inside its transaction.
+    Transaction,
+}
+
+impl Receiver {
+    fn self_param(self) -> &'static str {
+        match self {
+            Receiver::Database => "&self",
+            Receiver::Transaction => "&mut self",
+        }
+    }
+}
+
+/// The statement a batch query runs, which decides how it is rewritten to
+/// take every item at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BatchStatement {
+    Select,
+    Insert,
+    Update,
+    Delete,
+}
 
 pub struct RustGenerator {
     request: GenerateRequest,
     options: PluginOptions,
+    type_mapper: TypeMapper,
 }
 
 impl RustGenerator {
-    pub fn new(request: GenerateRequest) -> Self {
-        let options = serde_json::from_value(request.plugin_options.clone())
-            .unwrap_or_else(|_| PluginOptions::default());
+    pub fn new(mut request: GenerateRequest) -> Result<Self> {
+        let mut options: PluginOptions = if request.plugin_options.is_null() {
+            PluginOptions::default()
+        } else {
+            serde_json::from_value(request.plugin_options.clone())
+                .context("Failed to parse plugin options")?
+        };
+        Self::define_catalog_types(&mut request, &mut options)?;
+        let overrides = options.overrides.take().unwrap_or_default();
+        let type_mapper = TypeMapper::new(
+            &request.catalog,
+            overrides,
+            options.emit_domain_newtypes.unwrap_or(false),
+        );
+
+        Ok(Self {
+            request,
+            options,
+            type_mapper,
+        })
+    }
+
+    /// Adds what the `composite_types` and `domains` options define to the
+    /// catalog, since sqlc reports neither composite attributes nor domains.
+    fn define_catalog_types(
+        request: &mut GenerateRequest,
+        options: &mut PluginOptions,
+    ) -> Result<()> {
+        let catalog = &mut request.catalog;
+        let default_schema = catalog.default_schema.clone();
+        let split = |name: &str| match name.split_once('.') {
+            Some((schema, name)) => (schema.to_string(), name.to_string()),
+            None => (default_schema.clone(), name.to_string()),
+        };
+        let type_name = |name: &str| {
+            let (schema, name) = name.split_once('.').unwrap_or(("", name));
+            Identifier {
+                catalog: String::new(),
+                schema: schema.to_string(),
+                name: name.to_string(),
+            }
+        };
 
-        Self { request, options }
+        for definition in options.composite_types.take().unwrap_or_default() {
+            let (schema, name) = split(&definition.name);
+            let composite = catalog
+                .schemas
+                .iter_mut()
+                .filter(|s| s.name == schema)
+                .flat_map(|s| &mut s.composite_types)
+                .find(|composite| composite.name == name)
+                .with_context(|| {
+                    format!(
+                        "composite_types: {} is not a composite type in the catalog",
+                        definition.name
+                    )
+                })?;
+            composite.columns = definition
+                .attributes
+                .into_iter()
+                .map(|attribute| {
+                    let sql_type = type_name(&attribute.db_type);
+                    Self::attribute_column(attribute.name, sql_type, attribute.is_array)
+                })
+                .collect();
+        }
+
+        for definition in options.domains.take().unwrap_or_default() {
+            let (schema, name) = split(&definition.name);
+            let Some(schema) = catalog.schemas.iter_mut().find(|s| s.name == schema) else {
+                bail!(
+                    "domains: schema {schema} of {} is not in the catalog",
+                    definition.name
+                );
+            };
+            schema.domains.push(Domain {
+                name,
+                comment: definition.comment,
+                base_type: type_name(&definition.base_type),
+                is_array: definition.is_array,
+            });
+        }
+        Ok(())
+    }
+
+    /// A composite attribute as a column, which Postgres always allows to be
+    /// null.
+    fn attribute_column(name: String, sql_type: Identifier, is_array: bool) -> Column {
+        Column {
+            name,
+            not_null: false,
+            is_array,
+            comment: None,
+            length: None,
+            is_named_param: false,
+            is_func_call: false,
+            scope: None,
+            table: None,
+            table_alias: None,
+            r#type: Some(sql_type),
+            is_sqlc_slice: false,
+            embed_table: None,
+            original_name: None,
+            unsigned: false,
+            array_dims: i32::from(is_array),
+        }
     }
 
     pub fn generate(&self) -> Result<GenerateResponse> {
+        for type_override in self.type_mapper.overrides() {
+            type_override.validate()?;
+        }
+        for query in &self.request.queries {
+            Self::check_param_numbers(query)?;
+            Self::check_param_annotations(query)?;
+            self.check_multiranges(query)?;
+            self.check_copy_types(query)?;
+        }
+        self.check_identifiers()?;
+
         let mut files = Vec::new();
 
         // Generate models file
         let models_content = self.generate_models()?;
         files.push(File {
-            name: self
-                .options
-                .output_models_file_name
-                .clone()
-                .unwrap_or_else(|| "models.rs".to_string()),
+            name: self.models_file_name(),
             contents: models_content.into_bytes(),
         });
 
         // Generate queries file
         let queries_content = self.generate_queries()?;
         files.push(File {
-            name: self
-                .options
-                .output_db_file_name
-                .clone()
-                .unwrap_or_else(|| "queries.rs".to_string()),
+            name: self.db_file_name(),
             contents: queries_content.into_bytes(),
         });
 
+        // Generate batch file
+        if self.has_batch_queries() {
+            let batch_content = self.generate_batch()?;
+            files.push(File {
+                name: self.batch_file_name(),
+                contents: batch_content.into_bytes(),
+            });
+        }
+
+        // Generate querier file
+        if self.emits_interface() {
+            let querier_content = self.generate_querier()?;
+            files.push(File {
+                name: self.querier_file_name(),
+                contents: querier_content.into_bytes(),
+            });
+        }
+
+        // Generate mock file
+        if self.emits_mock() {
+            let mock_content = self.generate_mock()?;
+            files.push(File {
+                name: self.mock_file_name(),
+                contents: mock_content.into_bytes(),
+            });
+        }
+
         // Generate lib.rs file
         let lib_content = self.generate_lib()?;
         files.push(File {
@@ -51,15 +488,168 @@ impl RustGenerator {
         Ok(GenerateResponse { files })
     }
 
+    fn models_file_name(&self) -> String {
+        self.options
+            .output_models_file_name
+            .clone()
+            .unwrap_or_else(|| "models.rs".to_string())
+    }
+
+    fn db_file_name(&self) -> String {
+        self.options
+            .output_db_file_name
+            .clone()
+            .unwrap_or_else(|| "queries.rs".to_string())
+    }
+
+    fn batch_file_name(&self) -> String {
+        self.options
+            .output_batch_file_name
+            .clone()
+            .unwrap_or_else(|| "batch.rs".to_string())
+    }
+
+    fn querier_file_name(&self) -> String {
+        self.options
+            .output_querier_file_name
+            .clone()
+            .unwrap_or_else(|| "querier.rs".to_string())
+    }
+
+    fn mock_file_name(&self) -> String {
+        self.options
+            .output_mock_file_name
+            .clone()
+            .unwrap_or_else(|| "mock.rs".to_string())
+    }
+
+    /// The `cfg` predicate gating the mock module.
+    fn mock_cfg(&self) -> &str {
+        self.options.mock_cfg.as_deref().unwrap_or("test")
+    }
+
+    /// The mock implements `Querier`, so emitting it implies the trait.
+    fn emits_interface(&self) -> bool {
+        self.options.emit_interface.unwrap_or(false) || self.emits_mock()
+    }
+
+    fn emits_mock(&self) -> bool {
+        self.options.emit_mock.unwrap_or(false)
+    }
+
+    fn emits_domain_newtypes(&self) -> bool {
+        self.options.emit_domain_newtypes.unwrap_or(false)
+    }
+
+    /// Rejects names that collide after case conversion, with each other or
+    /// with items of the generated code, since they would produce duplicate
+    /// or shadowed items.
+    fn check_identifiers(&self) -> Result<()> {
+        fn claim(
+            names: &mut HashMap<String, String>,
+            kind: &str,
+            name: &str,
+            source: String,
+        ) -> Result<()> {
+            match names.insert(ident::unraw(name).to_string(), source.clone()) {
+                Some(previous) => bail!("{source} and {previous} both generate {kind} `{name}`"),
+                None => Ok(()),
+            }
+        }
+
+        let mut types = HashMap::new();
+        for name in RESERVED_TYPE_NAMES {
+            types.insert(name.to_string(), "the generated code".to_string());
+        }
+        let mut methods = HashMap::new();
+        for name in RESERVED_METHOD_NAMES {
+            methods.insert(name.to_string(), "the generated code".to_string());
+        }
+        let mut consts = HashMap::new();
+        consts.insert(
+            "COPY_CHUNK_SIZE".to_string(),
+            "the generated code".to_string(),
+        );
+
+        for schema in &self.request.catalog.schemas {
+            for table in &schema.tables {
+                let source = format!("table {}", table.rel.name);
+                claim(&mut types, "type", &Self::table_struct_name(table), source)?;
+            }
+            for enum_def in &schema.enums {
+                let name = ident::pascal_case(&enum_def.name);
+                claim(&mut types, "type", &name, format!("enum {}", enum_def.name))?;
+
+                let mut variants = HashMap::new();
+                for (val, variant) in enum_def.vals.iter().zip(Self::variant_names(enum_def)) {
+                    let source = format!("enum value '{val}'");
+                    claim(
+                        &mut variants,
+                        "variant",
+                        &format!("{name}::{variant}"),
+                        source,
+                    )?;
+                }
+            }
+            for composite in &schema.composite_types {
+                if !composite.columns.is_empty() {
+                    let name = ident::pascal_case(&composite.name);
+                    let source = format!("composite type {}", composite.name);
+                    claim(&mut types, "type", &name, source)?;
+                }
+            }
+            for domain in &schema.domains {
+                if self.emits_domain_newtypes() {
+                    let name = ident::pascal_case(&domain.name);
+                    claim(&mut types, "type", &name, format!("domain {}", domain.name))?;
+                }
+            }
+        }
+
+        for query in &self.request.queries {
+            let source = format!("query {}", query.name);
+            if self.needs_row_struct(query) {
+                let name = Self::row_struct_name(query);
+                claim(&mut types, "type", &name, source.clone())?;
+            }
+            if self.uses_params_struct(query) {
+                let name = Self::params_struct_name(query);
+                claim(&mut types, "type", &name, source.clone())?;
+            }
+            claim(
+                &mut methods,
+                "method",
+                &Self::method_name(query),
+                source.clone(),
+            )?;
+            if query.cmd == ":many" && self.emits_stream(query) {
+                let name = Self::stream_method_name(query);
+                claim(&mut methods, "method", &name, source.clone())?;
+            }
+            claim(&mut consts, "const", &Self::sql_const_name(query), source)?;
+        }
+
+        Ok(())
+    }
+
+    /// Module name `lib.rs` declares for a generated file.
+    fn module_name(file_name: &str) -> String {
+        file_name
+            .strip_suffix(".rs")
+            .unwrap_or(file_name)
+            .to_snake_case()
+    }
+
     fn generate_models(&self) -> Result<String> {
         let mut output = String::new();
 
-        // Add imports
-        for import in TypeMapper::get_rust_imports() {
-            output.push_str(import);
+        output.push_str(&self.generate_imports(false, &["use sqlx::FromRow;".to_string()]));
+        output.push('\n');
+
+        if self.uses_range_types() {
+            output.push_str(PG_RANGE_SERDE);
             output.push('\n');
         }
-        output.push('\n');
 
         // Generate structs for each table
         for schema in &self.request.catalog.schemas {
@@ -70,268 +660,2276 @@ impl RustGenerator {
 
             // Generate enums
             for enum_def in &schema.enums {
-                output.push_str(&self.generate_enum(enum_def)?);
+                output.push_str(&self.generate_enum(&schema.name, enum_def)?);
                 output.push_str("\n\n");
             }
+
+            // Generate domain newtypes
+            if self.emits_domain_newtypes() {
+                for domain in &schema.domains {
+                    output.push_str(&self.generate_domain(domain));
+                    output.push_str("\n\n");
+                }
+            }
+
+            // Generate composite types
+            for composite in &schema.composite_types {
+                if let Some(composite_struct) = self.generate_composite(&schema.name, composite)? {
+                    output.push_str(&composite_struct);
+                    output.push_str("\n\n");
+                }
+            }
         }
 
         Ok(output)
     }
 
     fn generate_table_struct(&self, table: &Table) -> Result<String> {
-        let struct_name = table.rel.name.to_pascal_case();
+        let struct_name = Self::table_struct_name(table);
+        self.generate_struct(
+            &struct_name,
+            table.comment.as_deref(),
+            Some(&table.rel),
+            &table.columns,
+        )
+    }
+
+    fn table_struct_name(table: &Table) -> String {
+        ident::pascal_case(&table.rel.name)
+    }
+
+    fn generate_struct(
+        &self,
+        struct_name: &str,
+        comment: Option<&str>,
+        table: Option<&Identifier>,
+        columns: &[Column],
+    ) -> Result<String> {
         let mut output = String::new();
 
         // Add comment if available
-        if let Some(comment) = &table.comment {
+        if let Some(comment) = comment {
             output.push_str(&format!("/// {comment}\n"));
         }
 
         // Add derives
-        output.push_str("#[derive(Debug, Clone, Serialize, Deserialize");
-        if self.options.emit_json_tags.unwrap_or(false) {
-            output.push_str(", FromRow");
-        }
-        output.push_str(")]\n");
-
-        // Add serde attributes
-        if self.options.emit_json_tags.unwrap_or(false) {
-            let case_style = self
-                .options
-                .json_tags_case_style
-                .as_deref()
-                .unwrap_or("snake_case");
-            output.push_str(&format!("#[serde(rename_all = \"{case_style}\")]\n"));
-        }
+        output.push_str("#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]\n");
 
+        output.push_str(&self.serde_attributes());
         output.push_str(&format!("pub struct {struct_name} {{\n"));
+        output.push_str(&self.generate_fields(table, columns, true)?);
+        output.push_str("}\n");
+        Ok(output)
+    }
 
-        for column in &table.columns {
+    fn serde_attributes(&self) -> String {
+        if !self.options.emit_json_tags.unwrap_or(false) {
+            return String::new();
+        }
+        let case_style = self
+            .options
+            .json_tags_case_style
+            .as_deref()
+            .unwrap_or("snake_case");
+        format!("#[serde(rename_all = \"{case_style}\")]\n")
+    }
+
+    /// Struct fields for `columns`. Rows are decoded by column name, so
+    /// fields named differently get `#[sqlx(rename)]` when `by_name` is set;
+    /// composites are decoded by position and don't take it.
+    fn generate_fields(
+        &self,
+        table: Option<&Identifier>,
+        columns: &[Column],
+        by_name: bool,
+    ) -> Result<String> {
+        let mut output = String::new();
+        let mut seen_columns = HashSet::new();
+        for (column, field_name) in columns.iter().zip(Self::field_names(columns, "column")) {
             if let Some(comment) = &column.comment {
                 output.push_str(&format!("    /// {comment}\n"));
             }
 
-            let field_name = column.name.to_snake_case();
-            let field_type =
-                TypeMapper::sql_to_rust_type(&column.r#type, column.not_null, column.is_array);
+            let field_type = match &column.embed_table {
+                Some(_) => Self::table_struct_name(self.embedded_table(column)?),
+                None => self.column_type(table, column, column.not_null, column.is_array),
+            };
+            if column.embed_table.is_some() {
+                output.push_str("    #[sqlx(flatten)]\n");
+            }
+            if Self::is_range_type(&field_type) {
+                output.push_str("    #[serde(with = \"pg_range\")]\n");
+            }
 
-            if self.options.emit_json_tags.unwrap_or(false) && field_name != column.name {
-                output.push_str(&format!("    #[serde(rename = \"{}\")]\n", column.name));
+            // Repeated and unnamed columns can't be mapped back by name
+            let first_use = !column.name.is_empty() && seen_columns.insert(&column.name);
+            if first_use && ident::unraw(&field_name) != column.name {
+                if by_name {
+                    output.push_str(&format!("    #[sqlx(rename = \"{}\")]\n", column.name));
+                }
+                if self.options.emit_json_tags.unwrap_or(false) {
+                    output.push_str(&format!("    #[serde(rename = \"{}\")]\n", column.name));
+                }
             }
 
             output.push_str(&format!("    pub {field_name}: {field_type},\n"));
         }
 
-        output.push_str("}\n");
         Ok(output)
     }
 
-    fn generate_enum(&self, enum_def: &Enum) -> Result<String> {
-        let enum_name = enum_def.name.to_pascal_case();
+    /// Rust field names for a list of columns, numbering unnamed columns and
+    /// suffixing names that collide after case conversion so every field is
+    /// unique.
+    fn field_names<'a>(
+        columns: impl IntoIterator<Item = &'a Column>,
+        unnamed: &str,
+    ) -> Vec<String> {
+        let mut used = HashSet::new();
+
+        columns
+            .into_iter()
+            .enumerate()
+            .map(|(i, column)| {
+                let mut base = ident::snake_case(&column.name);
+                if base.is_empty() {
+                    base = format!("{unnamed}_{}", i + 1);
+                }
+
+                let mut name = base.clone();
+                let mut count = 1;
+                while !used.insert(ident::unraw(&name).to_string()) {
+                    count += 1;
+                    name = ident::sanitize(format!("{}_{count}", ident::unraw(&base)));
+                }
+                name
+            })
+            .collect()
+    }
+
+    /// A Rust enum for a Postgres enum, encoded and decoded by sqlx as the
+    /// catalog type.
+    fn generate_enum(&self, schema: &str, enum_def: &Enum) -> Result<String> {
+        let enum_name = ident::pascal_case(&enum_def.name);
         let mut output = String::new();
 
         if let Some(comment) = &enum_def.comment {
             output.push_str(&format!("/// {comment}\n"));
         }
 
-        output.push_str("#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]\n");
+        output.push_str(&format!(
+            "#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, {})]\n",
+            self.sqlx_type_derives(schema)
+        ));
+        if schema == self.request.catalog.default_schema {
+            output.push_str(&format!("#[sqlx(type_name = \"{}\")]\n", enum_def.name));
+        }
         output.push_str(&format!("pub enum {enum_name} {{\n"));
 
-        for val in &enum_def.vals {
-            let variant_name = val.to_pascal_case();
+        for (val, variant_name) in enum_def.vals.iter().zip(Self::variant_names(enum_def)) {
             output.push_str(&format!("    #[serde(rename = \"{val}\")]\n"));
+            output.push_str(&format!("    #[sqlx(rename = \"{val}\")]\n"));
             output.push_str(&format!("    {variant_name},\n"));
         }
 
-        output.push_str("}\n");
+        output.push_str("}\n\n");
+        output.push_str(&self.generate_pg_type_impls(schema, &enum_def.name, &enum_name));
         Ok(output)
     }
 
-    fn generate_queries(&self) -> Result<String> {
+    /// A struct for a composite type, encoded and decoded by sqlx as a
+    /// record. sqlc doesn't report attributes for every composite, and one
+    /// without them is left out and mapped like an unknown type.
+    fn generate_composite(
+        &self,
+        schema: &str,
+        composite: &CompositeType,
+    ) -> Result<Option<String>> {
+        if composite.columns.is_empty() {
+            return Ok(None);
+        }
+
+        let struct_name = ident::pascal_case(&composite.name);
+        let rel = Identifier {
+            catalog: String::new(),
+            schema: schema.to_string(),
+            name: composite.name.clone(),
+        };
         let mut output = String::new();
 
-        // Add imports
-        for import in TypeMapper::get_rust_imports() {
-            output.push_str(import);
-            output.push('\n');
-        }
-        for import in TypeMapper::get_sqlx_imports() {
-            output.push_str(import);
-            output.push('\n');
+        if let Some(comment) = &composite.comment {
+            output.push_str(&format!("/// {comment}\n"));
         }
-        output.push_str("use crate::models::*;\n\n");
 
-        // Generate database struct
-        output.push_str("pub struct Database {\n");
-        output.push_str("    pool: Pool<Postgres>,\n");
+        output.push_str(&format!(
+            "#[derive(Debug, Clone, Serialize, Deserialize, {})]\n",
+            self.sqlx_type_derives(schema)
+        ));
+        if schema == self.request.catalog.default_schema {
+            output.push_str(&format!("#[sqlx(type_name = \"{}\")]\n", composite.name));
+        }
+        output.push_str(&self.serde_attributes());
+        output.push_str(&format!("pub struct {struct_name} {{\n"));
+        output.push_str(&self.generate_fields(Some(&rel), &composite.columns, false)?);
         output.push_str("}\n\n");
+        output.push_str(&self.generate_pg_type_impls(schema, &composite.name, &struct_name));
+        Ok(Some(output))
+    }
 
-        output.push_str("impl Database {\n");
-        output.push_str("    pub fn new(pool: Pool<Postgres>) -> Self {\n");
-        output.push_str("        Self { pool }\n");
-        output.push_str("    }\n\n");
+    /// A newtype for a domain, encoded and decoded as its base type since
+    /// Postgres reports domain values with the base type's OID.
+    fn generate_domain(&self, domain: &Domain) -> String {
+        let mut output = String::new();
 
-        // Generate methods for each query
-        for query in &self.request.queries {
-            output.push_str(&self.generate_query_method(query)?);
-            output.push('\n');
+        if let Some(comment) = &domain.comment {
+            output.push_str(&format!("/// {comment}\n"));
         }
 
-        output.push_str("}\n");
-        Ok(output)
+        output.push_str("#[derive(Debug, Clone, Serialize, Deserialize, sqlx::Type)]\n");
+        // Arrays of arrays aren't supported
+        if domain.is_array {
+            output.push_str("#[sqlx(transparent, no_pg_array)]\n");
+        } else {
+            output.push_str("#[sqlx(transparent)]\n");
+        }
+        let base_type = self.type_mapper.domain_base_type(domain);
+        let serde_with = if Self::is_range_type(&base_type) {
+            "#[serde(with = \"pg_range\")] "
+        } else {
+            ""
+        };
+        output.push_str(&format!(
+            "pub struct {}({serde_with}pub {base_type});\n",
+            ident::pascal_case(&domain.name),
+        ));
+        output
     }
 
-    fn generate_query_method(&self, query: &Query) -> Result<String> {
-        let method_name = query.name.to_snake_case();
-        let mut output = String::new();
+    /// Whether a field type is a `PgRange`, possibly in `Option`s and `Vec`s,
+    /// which serializes through the `pg_range` module.
+    fn is_range_type(rust_type: &str) -> bool {
+        let mut inner = rust_type;
+        while let Some(rest) = inner
+            .strip_prefix("Option<")
+            .or_else(|| inner.strip_prefix("Vec<"))
+        {
+            inner = rest;
+        }
+        inner.starts_with("sqlx::postgres::types::PgRange<")
+    }
 
-        // Determine return type
-        let return_type = self.get_query_return_type(query)?;
+    /// Whether any generated struct has a range field, so the models file
+    /// needs the `pg_range` module.
+    fn uses_range_types(&self) -> bool {
+        let schemas = &self.request.catalog.schemas;
+        let table_columns = schemas
+            .iter()
+            .flat_map(|schema| &schema.tables)
+            .flat_map(|table| {
+                table
+                    .columns
+                    .iter()
+                    .map(|column| (Some(&table.rel), column))
+            });
+        let composite_columns = schemas
+            .iter()
+            .flat_map(|schema| &schema.composite_types)
+            .flat_map(|composite| composite.columns.iter().map(|column| (None, column)));
+        let query_columns = self
+            .request
+            .queries
+            .iter()
+            .flat_map(|query| query.columns.iter().map(|column| (None, column)));
 
-        // Generate method signature
-        output.push_str(&format!("    pub async fn {method_name}(&self"));
+        let domain_ranges = self.emits_domain_newtypes()
+            && schemas
+                .iter()
+                .flat_map(|schema| &schema.domains)
+                .any(|domain| Self::is_range_type(&self.type_mapper.domain_base_type(domain)));
 
-        // Add parameters
-        for param in &query.params {
-            let param_name = format!("param_{}", param.number);
-            let param_type = TypeMapper::sql_to_rust_type(
-                &param.column.r#type,
-                param.column.not_null,
-                param.column.is_array,
-            );
-            output.push_str(&format!(", {param_name}: {param_type}"));
+        domain_ranges
+            || table_columns
+                .chain(composite_columns)
+                .chain(query_columns)
+                .filter(|(_, column)| column.embed_table.is_none())
+                .any(|(table, column)| {
+                    Self::is_range_type(&self.column_type(
+                        table,
+                        column,
+                        column.not_null,
+                        column.is_array,
+                    ))
+                })
+    }
+
+    /// sqlx derives for a type standing for a catalog type. sqlx matches
+    /// result columns by their bare type name but looks types up for binds by
+    /// a name that must be schema-qualified outside the search path, which
+    /// `#[sqlx(type_name)]` can't do both of. Types outside the default schema
+    /// therefore only derive the codecs and get a hand-written `sqlx::Type`.
+    fn sqlx_type_derives(&self, schema: &str) -> &'static str {
+        if schema == self.request.catalog.default_schema {
+            "sqlx::Type"
+        } else {
+            "sqlx::Encode, sqlx::Decode"
         }
+    }
 
-        output.push_str(&format!(") -> Result<{return_type}, SqlxError> {{\n"));
+    /// The `sqlx::Type` impl for types outside the default schema, see
+    /// `sqlx_type_derives`, and `PgHasArrayType`, which derives don't provide
+    /// for named types, so the type can be used in `Vec` columns and params.
+    fn generate_pg_type_impls(&self, schema: &str, name: &str, rust_name: &str) -> String {
+        let mut output = String::new();
+        let qualified = schema != self.request.catalog.default_schema;
 
-        // Generate query execution
+        if qualified {
+            output.push_str(&format!(
+                "impl sqlx::Type<sqlx::Postgres> for {rust_name} {{\n"
+            ));
+            output.push_str("    fn type_info() -> sqlx::postgres::PgTypeInfo {\n");
+            output.push_str(&format!(
+                "        sqlx::postgres::PgTypeInfo::with_name(\"{schema}.{name}\")\n"
+            ));
+            output.push_str("    }\n\n");
+            output.push_str("    fn compatible(ty: &sqlx::postgres::PgTypeInfo) -> bool {\n");
+            output.push_str(&format!(
+                "        *ty == Self::type_info() || sqlx::TypeInfo::name(ty) == \"{name}\"\n"
+            ));
+            output.push_str("    }\n");
+            output.push_str("}\n\n");
+        }
+
+        output.push_str(&format!(
+            "impl sqlx::postgres::PgHasArrayType for {rust_name} {{\n"
+        ));
+        output.push_str("    fn array_type_info() -> sqlx::postgres::PgTypeInfo {\n");
+        let array_name = if qualified {
+            format!("{schema}._{name}")
+        } else {
+            format!("_{name}")
+        };
         output.push_str(&format!(
-            "        let query = r#\"\n{}\n        \"#;\n\n",
-            query.text
+            "        sqlx::postgres::PgTypeInfo::with_name(\"{array_name}\")\n"
         ));
+        output.push_str("    }\n");
+        if qualified {
+            output
+                .push_str("\n    fn array_compatible(ty: &sqlx::postgres::PgTypeInfo) -> bool {\n");
+            output.push_str(&format!(
+                "        *ty == Self::array_type_info() || sqlx::TypeInfo::name(ty) == \"_{name}\"\n"
+            ));
+            output.push_str("    }\n");
+        }
+        output.push_str("}\n");
+        output
+    }
 
-        match query.cmd.as_str() {
-            ":one" => {
-                output.push_str("        let row = sqlx::query(query)\n");
-                for (i, _) in query.params.iter().enumerate() {
-                    output.push_str(&format!("            .bind(param_{})\n", i + 1));
-                }
-                output.push_str("            .fetch_one(&self.pool)\n");
-                output.push_str("            .await?;\n\n");
-                output.push_str(&self.generate_row_mapping(query)?);
+    /// Variant names for an enum's values, numbering values without any
+    /// identifier characters.
+    fn variant_names(enum_def: &Enum) -> Vec<String> {
+        enum_def
+            .vals
+            .iter()
+            .enumerate()
+            .map(|(i, val)| match ident::pascal_case(val) {
+                name if name.is_empty() => format!("Value{}", i + 1),
+                name => name,
+            })
+            .collect()
+    }
+
+    fn generate_queries(&self) -> Result<String> {
+        let mut output = String::new();
+
+        output.push_str(
+            &self.generate_imports(true, &[Self::crate_glob_import(&self.models_file_name())]),
+        );
+        output.push('\n');
+
+        // Generate row structs for queries that don't return a table model,
+        // and params structs for queries above the parameter limit
+        for query in &self.request.queries {
+            if let Some(row_struct) = self.generate_row_struct(query)? {
+                output.push_str(&row_struct);
+                output.push('\n');
             }
-            ":many" => {
-                output.push_str("        let rows = sqlx::query(query)\n");
-                for (i, _) in query.params.iter().enumerate() {
-                    output.push_str(&format!("            .bind(param_{})\n", i + 1));
-                }
-                output.push_str("            .fetch_all(&self.pool)\n");
+            if let Some(params_struct) = self.generate_params_struct(query)? {
+                output.push_str(&params_struct);
+                output.push('\n');
+            }
+        }
+
+        output.push_str(&self.generate_sql_consts()?);
+
+        // Generate database struct
+        if self.with_db_argument() {
+            output.push_str("#[derive(Debug, Default, Clone, Copy)]\n");
+            output.push_str("pub struct Database;\n\n");
+
+            output.push_str("impl Database {\n");
+            output.push_str("    pub fn new() -> Self {\n");
+            output.push_str("        Self\n");
+            output.push_str("    }\n\n");
+        } else {
+            output.push_str("pub struct Database {\n");
+            output.push_str("    pub(crate) pool: Pool<Postgres>,\n");
+            output.push_str("}\n\n");
+
+            output.push_str("impl Database {\n");
+            output.push_str("    pub fn new(pool: Pool<Postgres>) -> Self {\n");
+            output.push_str("        Self { pool }\n");
+            output.push_str("    }\n\n");
+            output.push_str(TRANSACTION_METHODS);
+            output.push('\n');
+        }
+
+        if self.emits_prepared() {
+            output.push_str(&self.generate_prepare_methods()?);
+            output.push('\n');
+        }
+        output.push_str(&self.generate_methods(Receiver::Database)?);
+        output.push_str("}\n");
+
+        if self.emits_transactions() {
+            output.push('\n');
+            output.push_str(TRANSACTION_SUPPORT);
+            output.push_str("\nimpl DatabaseTx {\n");
+            output.push_str(&self.generate_methods(Receiver::Transaction)?);
+            output.push_str("}\n");
+        }
+
+        if self.request.queries.iter().any(|q| q.cmd == ":copyfrom") {
+            output.push('\n');
+            output.push_str(COPY_FIELD_ENCODER);
+        }
+
+        Ok(output)
+    }
+
+    /// Methods for every query except batches, which live in their own file.
+    fn generate_methods(&self, receiver: Receiver) -> Result<String> {
+        let mut output = String::new();
+
+        for query in &self.request.queries {
+            if Self::is_batch(query) {
+                continue;
+            }
+            if query.cmd == ":copyfrom" {
+                output.push_str(&self.generate_copyfrom_method(query, receiver)?);
+                output.push('\n');
+                continue;
+            }
+            output.push_str(&self.generate_query_method(query, receiver)?);
+            output.push('\n');
+            if query.cmd == ":many" && self.emits_stream(query) {
+                output.push_str(&self.generate_stream_method(query, receiver)?);
+                output.push('\n');
+            }
+        }
+
+        Ok(output)
+    }
+
+    /// Whether `Database` gets `prepare_all` and `prepare_connection`.
+    fn emits_prepared(&self) -> bool {
+        self.options.emit_prepared_queries.unwrap_or(false)
+    }
+
+    /// `prepare_all`, which prepares every query up front so a schema that
+    /// drifted from the generated SQL fails at startup rather than on first
+    /// use. `:copyfrom` queries can't be prepared and are left out.
+    fn generate_prepare_methods(&self) -> Result<String> {
+        let mut output = String::new();
+
+        if self.with_db_argument() {
+            output.push_str(
+                "    /// Prepares every query on `db`, which caches the statements for\n",
+            );
+            output.push_str("    /// later calls on the same connection.\n");
+            output.push_str(
+                "    pub async fn prepare_all(&self, db: &mut sqlx::PgConnection) -> Result<(), SqlxError> {\n",
+            );
+            output.push_str("        Self::prepare_connection(db).await\n");
+        } else {
+            output.push_str(
+                "    /// Prepares every query on one connection of the pool, failing if\n",
+            );
+            output.push_str("    /// any of them no longer matches the schema.\n");
+            output.push_str("    pub async fn prepare_all(&self) -> Result<(), SqlxError> {\n");
+            output.push_str("        let mut conn = self.pool.acquire().await?;\n");
+            output.push_str("        Self::prepare_connection(&mut conn).await\n");
+        }
+        output.push_str("    }\n\n");
+
+        output.push_str(
+            "    /// Prepares every query on `conn` and caches the statements on it, e.g.\n",
+        );
+        output.push_str(
+            "    /// from `PoolOptions::after_connect` to warm up every new connection.\n",
+        );
+        output.push_str(
+            "    pub async fn prepare_connection(conn: &mut sqlx::PgConnection) -> Result<(), SqlxError> {\n",
+        );
+        let queries: Vec<_> = self
+            .request
+            .queries
+            .iter()
+            .filter(|q| q.cmd != ":copyfrom")
+            .collect();
+        output.push_str(&format!(
+            "        let queries: [&str; {}] = [\n",
+            queries.len()
+        ));
+        for query in queries {
+            output.push_str(&format!("            {},\n", Self::sql_const_name(query)));
+        }
+        output.push_str("        ];\n\n");
+        output.push_str("        for query in queries {\n");
+        output.push_str("            sqlx::Executor::prepare(&mut *conn, query).await?;\n");
+        output.push_str("        }\n");
+        output.push_str("        Ok(())\n");
+        output.push_str("    }\n");
+
+        Ok(output)
+    }
+
+    /// Whether `Database` gets `begin`/`transaction` and a `DatabaseTx` is
+    /// generated. A stateless `Database` runs on whatever transaction the
+    /// caller passes in instead.
+    fn emits_transactions(&self) -> bool {
+        !self.with_db_argument()
+    }
+
+    /// `:copyfrom` methods stream their items to Postgres with
+    /// `COPY ... FROM STDIN (FORMAT binary)`, encoding every field with the
+    /// same sqlx `Encode` impl that binding the parameter would use.
+    fn generate_copyfrom_method(&self, query: &Query, receiver: Receiver) -> Result<String> {
+        let mut output = String::new();
+
+        let (_, item_name) = self.get_item_type(query)?;
+        let fields = if self.uses_params_struct(query) {
+            Self::param_names(query)
+                .into_iter()
+                .map(|name| format!("params.{name}"))
+                .collect()
+        } else {
+            vec![item_name.clone()]
+        };
+
+        output.push_str(&format!(
+            "    pub {} {{\n",
+            self.method_signature(query, receiver, false)?
+        ));
+        let sql = Self::sql_const_name(query);
+        if self.with_db_argument() {
+            output.push_str(&format!(
+                "        let mut copy = db.copy_in_raw({sql}).await?;\n\n"
+            ));
+        } else if receiver == Receiver::Transaction {
+            output.push_str(&format!(
+                "        let mut copy = self.tx.copy_in_raw({sql}).await?;\n\n"
+            ));
+        } else {
+            output.push_str("        let mut conn = self.pool.acquire().await?;\n");
+            output.push_str(&format!(
+                "        let mut copy = conn.copy_in_raw({sql}).await?;\n\n"
+            ));
+        }
+        output.push_str(
+            "        // Binary COPY header: signature, flags and header extension length\n",
+        );
+        output.push_str("        let mut buf = b\"PGCOPY\\n\\xff\\r\\n\\0\".to_vec();\n");
+        output.push_str("        buf.extend_from_slice(&0i32.to_be_bytes());\n");
+        output.push_str("        buf.extend_from_slice(&0i32.to_be_bytes());\n\n");
+        output.push_str(&format!("        for {item_name} in items {{\n"));
+        output.push_str(&format!(
+            "            buf.extend_from_slice(&{}i16.to_be_bytes());\n",
+            fields.len()
+        ));
+        for field in &fields {
+            output.push_str(&format!(
+                "            encode_copy_field(&mut buf, &{field});\n"
+            ));
+        }
+        output.push_str("            if buf.len() >= COPY_CHUNK_SIZE {\n");
+        output.push_str("                copy.send(std::mem::take(&mut buf)).await?;\n");
+        output.push_str("            }\n");
+        output.push_str("        }\n\n");
+        output.push_str("        // File trailer\n");
+        output.push_str("        buf.extend_from_slice(&(-1i16).to_be_bytes());\n");
+        output.push_str("        copy.send(buf).await?;\n");
+        output.push_str("        copy.finish().await\n");
+
+        output.push_str("    }\n");
+        Ok(output)
+    }
+
+    /// Whether methods take the executor as a `db` argument instead of
+    /// running on the pool owned by `Database`.
+    fn with_db_argument(&self) -> bool {
+        self.options.emit_methods_with_db_argument.unwrap_or(false)
+    }
+
+    /// The executor expression queries run on.
+    fn executor(&self, receiver: Receiver) -> &'static str {
+        if self.with_db_argument() {
+            "db"
+        } else if receiver == Receiver::Transaction {
+            "&mut *self.tx"
+        } else {
+            "&self.pool"
+        }
+    }
+
+    /// The connection argument of batch and copy methods, which run several
+    /// statements and so need a connection rather than any executor.
+    fn connection_param(&self) -> &'static str {
+        if self.with_db_argument() {
+            "        db: &mut sqlx::PgConnection,\n"
+        } else {
+            ""
+        }
+    }
+
+    /// Signature of the method generated for a query, from `async fn` up to
+    /// the return type. `querier` selects the form used by `Querier` and its
+    /// impls: trait methods can't bind arguments as `mut`, and their futures
+    /// must be `Send`, so everything they capture needs a `Send` bound.
+    fn method_signature(&self, query: &Query, receiver: Receiver, querier: bool) -> Result<String> {
+        let (head, return_type) = self.method_head(query, receiver, querier)?;
+        Ok(format!("async fn {head} -> {return_type}"))
+    }
+
+    /// Declaration of a `Querier` method, returning a `Send` future instead
+    /// of using `async fn`.
+    fn querier_method_declaration(&self, query: &Query) -> Result<String> {
+        let (head, return_type) = self.method_head(query, Receiver::Database, true)?;
+        Ok(format!(
+            "fn {head} -> impl std::future::Future<Output = {return_type}> + Send"
+        ))
+    }
+
+    /// Name and parameter list of the method generated for a query, along
+    /// with its return type.
+    fn method_head(
+        &self,
+        query: &Query,
+        receiver: Receiver,
+        querier: bool,
+    ) -> Result<(String, String)> {
+        let method_name = Self::method_name(query);
+        let return_type = self.get_query_return_type(query)?;
+        let send = if querier { " + Send" } else { "" };
+
+        if Self::takes_items(query) {
+            let (item_type, _) = self.get_item_type(query)?;
+            let mut head = format!(
+                "{method_name}(\n        {},\n{}        items: impl IntoIterator<Item = {item_type}>{send},\n",
+                receiver.self_param(),
+                self.connection_param()
+            );
+            if Self::is_batch(query) {
+                let callback = if querier { "f" } else { "mut f" };
+                head.push_str(&format!(
+                    "        {callback}: impl FnMut(usize, {return_type}){send},\n    )"
+                ));
+                return Ok((head, "Result<(), SqlxError>".to_string()));
+            }
+            head.push_str("    )");
+            return Ok((head, format!("Result<{return_type}, SqlxError>")));
+        }
+
+        // Anonymous lifetimes aren't allowed in `impl Trait` arguments of a
+        // method returning `impl Future`, so the querier form names it.
+        let mut head = if self.with_db_argument() {
+            let lifetime = if querier { "'c" } else { "'_" };
+            let generics = if querier { "<'c>" } else { "" };
+            format!(
+                "{method_name}{generics}({}, db: impl sqlx::Executor<{lifetime}, Database = Postgres>",
+                receiver.self_param()
+            )
+        } else {
+            format!("{method_name}({}", receiver.self_param())
+        };
+        head.push_str(&self.generate_method_params(query, None));
+        head.push(')');
+        Ok((head, format!("Result<{return_type}, SqlxError>")))
+    }
+
+    /// Arguments forwarding a call to the method generated for a query.
+    fn method_call_args(&self, query: &Query) -> Vec<String> {
+        let mut args = Vec::new();
+        if self.with_db_argument() {
+            args.push("db".to_string());
+        }
+
+        if Self::takes_items(query) {
+            args.push("items".to_string());
+            if Self::is_batch(query) {
+                args.push("f".to_string());
+            }
+        } else if self.uses_params_struct(query) {
+            args.push("params".to_string());
+        } else {
+            args.extend(Self::argument_names(query));
+        }
+        args
+    }
+
+    /// The parameter list after `&self`, either positional arguments or a
+    /// single params struct. `lifetime` names the lifetime of `sqlc.slice()`
+    /// arguments, which stream methods need to borrow for as long as the
+    /// returned stream.
+    fn generate_method_params(&self, query: &Query, lifetime: Option<&str>) -> String {
+        if self.uses_params_struct(query) {
+            return format!(", params: {}", Self::params_struct_name(query));
+        }
+
+        query
+            .params
+            .iter()
+            .zip(Self::argument_names(query))
+            .map(|(param, param_name)| {
+                let param_type = if param.column.is_sqlc_slice {
+                    let lifetime = lifetime.map(|l| format!("{l} ")).unwrap_or_default();
+                    format!("&{lifetime}[{}]", self.slice_element_type(param))
+                } else {
+                    self.param_type(query, param)
+                };
+                format!(", {param_name}: {param_type}")
+            })
+            .collect()
+    }
+
+    fn method_name(query: &Query) -> String {
+        ident::snake_case(&query.name)
+    }
+
+    fn stream_method_name(query: &Query) -> String {
+        ident::snake_case(&format!("{}_stream", query.name))
+    }
+
+    /// Whether a `:many` query also gets a `<name>_stream` method, either
+    /// through `emit_stream_methods` or a `@stream` annotation.
+    fn emits_stream(&self, query: &Query) -> bool {
+        self.options.emit_stream_methods.unwrap_or(false)
+            || Self::annotations(query).any(|annotation| annotation == "@stream")
+    }
+
+    /// Streaming variant of a `:many` query that decodes rows as they arrive
+    /// instead of collecting them into a `Vec`.
+    fn generate_stream_method(&self, query: &Query, receiver: Receiver) -> Result<String> {
+        let method_name = Self::stream_method_name(query);
+        let row_type = self.get_row_type(query);
+        let mut output = String::new();
+
+        let self_param = match receiver {
+            Receiver::Database => "&'a self",
+            Receiver::Transaction => "&'a mut self",
+        };
+        output.push_str(&format!("    pub fn {method_name}<'a>({self_param}"));
+        if self.with_db_argument() {
+            output.push_str(", db: impl sqlx::Executor<'a, Database = Postgres> + 'a");
+        }
+        output.push_str(&self.generate_method_params(query, Some("'a")));
+        output.push_str(&format!(
+            ") -> impl futures::Stream<Item = Result<{row_type}, SqlxError>> + 'a {{\n"
+        ));
+
+        let sql = Self::sql_const_name(query);
+
+        output.push_str(&format!("        let rows = sqlx::query({sql})\n"));
+        output.push_str(&self.generate_binds(query));
+        output.push_str(&format!(
+            "            .fetch({});\n\n",
+            self.executor(receiver)
+        ));
+        output.push_str(&format!(
+            "        futures::StreamExt::map(rows, |row| row.and_then(|row| {}))\n",
+            self.generate_row_result(query)?
+        ));
+
+        output.push_str("    }\n");
+        Ok(output)
+    }
+
+    fn generate_query_method(&self, query: &Query, receiver: Receiver) -> Result<String> {
+        let mut output = String::new();
+
+        // Generate method signature
+        output.push_str(&format!(
+            "    pub {} {{\n",
+            self.method_signature(query, receiver, false)?
+        ));
+
+        // Generate query execution
+        let sql = Self::sql_const_name(query);
+
+        match query.cmd.as_str() {
+            ":one" if self.returns_option(query) => {
+                output.push_str(&format!("        let row = sqlx::query({sql})\n"));
+                output.push_str(&self.generate_binds(query));
+                output.push_str(&format!(
+                    "            .fetch_optional({})\n",
+                    self.executor(receiver)
+                ));
                 output.push_str("            .await?;\n\n");
-                output.push_str("        let mut results = Vec::new();\n");
-                output.push_str("        for row in rows {\n");
+                output.push_str("        let Some(row) = row else {\n");
+                output.push_str("            return Ok(None);\n");
+                output.push_str("        };\n");
                 output.push_str(&format!(
-                    "            {}",
+                    "        Ok(Some({}))\n",
                     self.generate_row_mapping(query)?
-                        .replace("row", "&row")
-                        .replace("Ok(", "results.push(")
                 ));
+            }
+            ":one" => {
+                output.push_str(&format!("        let row = sqlx::query({sql})\n"));
+                output.push_str(&self.generate_binds(query));
+                output.push_str(&format!(
+                    "            .fetch_one({})\n",
+                    self.executor(receiver)
+                ));
+                output.push_str("            .await?;\n\n");
+                output.push_str(&format!("        {}\n", self.generate_row_result(query)?));
+            }
+            ":many" => {
+                output.push_str(&format!("        let rows = sqlx::query({sql})\n"));
+                output.push_str(&self.generate_binds(query));
+                output.push_str(&format!(
+                    "            .fetch_all({})\n",
+                    self.executor(receiver)
+                ));
+                output.push_str("            .await?;\n\n");
+                output.push_str(&format!(
+                    "        rows.iter().map(|row| {}).collect()\n",
+                    self.generate_row_result(query)?
+                ));
+            }
+            ":exec" => {
+                output.push_str(&format!("        sqlx::query({sql})\n"));
+                output.push_str(&self.generate_binds(query));
+                output.push_str(&format!(
+                    "            .execute({})\n",
+                    self.executor(receiver)
+                ));
+                output.push_str("            .await?;\n\n");
+                output.push_str("        Ok(())\n");
+            }
+            ":execrows" => {
+                output.push_str(&format!("        let result = sqlx::query({sql})\n"));
+                output.push_str(&self.generate_binds(query));
+                output.push_str(&format!(
+                    "            .execute({})\n",
+                    self.executor(receiver)
+                ));
+                output.push_str("            .await?;\n\n");
+                output.push_str("        Ok(result.rows_affected())\n");
+            }
+            ":execresult" => {
+                output.push_str(&format!("        sqlx::query({sql})\n"));
+                output.push_str(&self.generate_binds(query));
+                output.push_str(&format!(
+                    "            .execute({})\n",
+                    self.executor(receiver)
+                ));
+                output.push_str("            .await\n");
+            }
+            cmd => bail!("Query {}: unknown command '{cmd}'", query.name),
+        }
+
+        output.push_str("    }\n");
+        Ok(output)
+    }
+
+    fn is_batch(query: &Query) -> bool {
+        matches!(
+            query.cmd.as_str(),
+            ":batchexec" | ":batchone" | ":batchmany"
+        )
+    }
+
+    /// Commands whose methods take an iterator of parameter sets.
+    fn takes_items(query: &Query) -> bool {
+        Self::is_batch(query) || query.cmd == ":copyfrom"
+    }
+
+    /// The item type of a batch or copy method and the name its loop binds
+    /// each item to.
+    fn get_item_type(&self, query: &Query) -> Result<(String, String)> {
+        match query.params.as_slice() {
+            [] => bail!(
+                "Query {}: {} queries need at least one parameter",
+                query.name,
+                query.cmd
+            ),
+            [param] if !self.uses_params_struct(query) => Ok((
+                self.param_type(query, param),
+                Self::argument_names(query).remove(0),
+            )),
+            _ => Ok((Self::params_struct_name(query), "params".to_string())),
+        }
+    }
+
+    fn has_batch_queries(&self) -> bool {
+        self.request.queries.iter().any(Self::is_batch)
+    }
+
+    fn generate_batch(&self) -> Result<String> {
+        let mut output = String::new();
+
+        output.push_str(&self.generate_imports(
+            true,
+            &[
+                Self::crate_glob_import(&self.models_file_name()),
+                Self::crate_glob_import(&self.db_file_name()),
+            ],
+        ));
+        output.push('\n');
+
+        output.push_str("impl Database {\n");
+        for query in self.request.queries.iter().filter(|q| Self::is_batch(q)) {
+            output.push_str(&self.generate_batch_method(query, Receiver::Database)?);
+            output.push('\n');
+        }
+        output.push_str("}\n");
+
+        if self.emits_transactions() {
+            output.push_str("\nimpl DatabaseTx {\n");
+            for query in self.request.queries.iter().filter(|q| Self::is_batch(q)) {
+                output.push_str(&self.generate_batch_method(query, Receiver::Transaction)?);
+                output.push('\n');
+            }
+            output.push_str("}\n");
+        }
+        Ok(output)
+    }
+
+    /// The `Querier` trait with one method per query, implemented for
+    /// `Database` by forwarding to its inherent methods.
+    fn generate_querier(&self) -> Result<String> {
+        let mut output = String::new();
+
+        output.push_str(&self.generate_imports(
+            true,
+            &[
+                Self::crate_glob_import(&self.models_file_name()),
+                Self::crate_glob_import(&self.db_file_name()),
+            ],
+        ));
+        output.push('\n');
+
+        output.push_str("pub trait Querier {\n");
+        for query in &self.request.queries {
+            output.push_str(&format!(
+                "    {};\n",
+                self.querier_method_declaration(query)?
+            ));
+        }
+        output.push_str("}\n\n");
+
+        output.push_str("impl Querier for Database {\n");
+        for (i, query) in self.request.queries.iter().enumerate() {
+            if i > 0 {
+                output.push('\n');
+            }
+            output.push_str(&format!(
+                "    {} {{\n",
+                self.method_signature(query, Receiver::Database, true)?
+            ));
+            // The inherent method holds the iterator across awaits, which
+            // would make the future depend on it being `Send` as well.
+            if Self::takes_items(query) {
+                output.push_str("        let items: Vec<_> = items.into_iter().collect();\n");
+            }
+            let mut args = vec!["self".to_string()];
+            args.extend(self.method_call_args(query));
+            output.push_str(&format!(
+                "        Database::{}({}).await\n",
+                Self::method_name(query),
+                args.join(", ")
+            ));
+            output.push_str("    }\n");
+        }
+        output.push_str("}\n");
+
+        Ok(output)
+    }
+
+    /// `MockDatabase`, a `Querier` that records every call with its typed
+    /// arguments and answers from results queued per method.
+    fn generate_mock(&self) -> Result<String> {
+        let mut output = String::new();
+
+        output.push_str(&self.generate_imports(
+            true,
+            &[
+                Self::crate_glob_import(&self.models_file_name()),
+                Self::crate_glob_import(&self.db_file_name()),
+                format!(
+                    "use crate::{}::Querier;",
+                    Self::module_name(&self.querier_file_name())
+                ),
+                "use std::collections::VecDeque;".to_string(),
+                "use std::sync::Mutex;".to_string(),
+            ],
+        ));
+        output.push('\n');
+
+        // One variant per query holding the arguments of a call
+        output.push_str("#[derive(Debug, Clone)]\n");
+        output.push_str("pub enum MockCall {\n");
+        for query in &self.request.queries {
+            let fields = self.mock_call_fields(query)?;
+            let variant = ident::pascal_case(&query.name);
+            if fields.is_empty() {
+                output.push_str(&format!("    {variant},\n"));
+            } else {
+                output.push_str(&format!("    {variant} {{\n"));
+                for (name, rust_type) in fields {
+                    output.push_str(&format!("        {name}: {rust_type},\n"));
+                }
+                output.push_str("    },\n");
+            }
+        }
+        output.push_str("}\n\n");
+
+        output.push_str("#[derive(Default)]\n");
+        output.push_str("pub struct MockDatabase {\n");
+        output.push_str("    calls: Mutex<Vec<MockCall>>,\n");
+        for query in &self.request.queries {
+            output.push_str(&format!(
+                "    {}_results: Mutex<VecDeque<Result<{}, SqlxError>>>,\n",
+                ident::unraw(&Self::method_name(query)),
+                self.get_query_return_type(query)?
+            ));
+        }
+        output.push_str("}\n\n");
+
+        output.push_str("impl MockDatabase {\n");
+        output.push_str("    pub fn new() -> Self {\n");
+        output.push_str("        Self::default()\n");
+        output.push_str("    }\n\n");
+        output.push_str("    /// All calls made so far, in order.\n");
+        output.push_str("    pub fn calls(&self) -> Vec<MockCall> {\n");
+        output.push_str("        self.calls.lock().unwrap().clone()\n");
+        output.push_str("    }\n");
+        for query in &self.request.queries {
+            let method_name = Self::method_name(query);
+            let method_name = ident::unraw(&method_name);
+            let doc = if Self::is_batch(query) {
+                "one item's result"
+            } else {
+                "the result of the next call"
+            };
+            output.push('\n');
+            output.push_str(&format!("    /// Queues {doc} for `{method_name}`.\n"));
+            output.push_str(&format!(
+                "    pub fn push_{method_name}(&self, result: Result<{}, SqlxError>) {{\n",
+                self.get_query_return_type(query)?
+            ));
+            output.push_str(&format!(
+                "        self.{method_name}_results.lock().unwrap().push_back(result);\n"
+            ));
+            output.push_str("    }\n");
+        }
+        output.push_str("}\n\n");
+
+        output.push_str("impl Querier for MockDatabase {\n");
+        for (i, query) in self.request.queries.iter().enumerate() {
+            if i > 0 {
+                output.push('\n');
+            }
+            output.push_str(&self.generate_mock_method(query)?);
+        }
+        output.push_str("}\n");
+
+        Ok(output)
+    }
+
+    /// Fields of a query's `MockCall` variant: its arguments, with batch and
+    /// copy items collected into a `Vec`.
+    fn mock_call_fields(&self, query: &Query) -> Result<Vec<(String, String)>> {
+        if Self::takes_items(query) {
+            let (item_type, _) = self.get_item_type(query)?;
+            return Ok(vec![("items".to_string(), format!("Vec<{item_type}>"))]);
+        }
+
+        if self.uses_params_struct(query) {
+            return Ok(vec![(
+                "params".to_string(),
+                Self::params_struct_name(query),
+            )]);
+        }
+
+        Ok(query
+            .params
+            .iter()
+            .zip(Self::argument_names(query))
+            .map(|(param, name)| (name, self.param_type(query, param)))
+            .collect())
+    }
+
+    fn generate_mock_method(&self, query: &Query) -> Result<String> {
+        let method_name = Self::method_name(query);
+        let method_name = ident::unraw(&method_name);
+        let variant = ident::pascal_case(&query.name);
+        let fields = self.mock_call_fields(query)?;
+        let mut output = String::new();
+
+        output.push_str(&format!(
+            "    {} {{\n",
+            self.method_signature(query, Receiver::Database, true)?
+        ));
+        if self.with_db_argument() {
+            output.push_str("        let _ = db;\n");
+        }
+        if Self::takes_items(query) {
+            output.push_str("        let items: Vec<_> = items.into_iter().collect();\n");
+        }
+
+        let call = if fields.is_empty() {
+            format!("MockCall::{variant}")
+        } else if Self::is_batch(query) {
+            format!("MockCall::{variant} {{ items: items.clone() }}")
+        } else {
+            let positional = !Self::takes_items(query) && !self.uses_params_struct(query);
+            let names: Vec<_> = fields
+                .iter()
+                .zip(&query.params)
+                .map(|((name, _), param)| {
+                    // Slices are borrowed by the method but owned by the call
+                    if positional && param.column.is_sqlc_slice {
+                        format!("{name}: {name}.to_vec()")
+                    } else {
+                        name.clone()
+                    }
+                })
+                .collect();
+            format!("MockCall::{variant} {{ {} }}", names.join(", "))
+        };
+
+        if Self::is_batch(query) {
+            output.push_str(&format!(
+                "        self.calls.lock().unwrap().push({call});\n"
+            ));
+            output.push_str("        let mut f = f;\n");
+            output.push_str("        for i in 0..items.len() {\n");
+            output.push_str(&format!(
+                "            let result = self.{method_name}_results.lock().unwrap().pop_front();\n"
+            ));
+            output.push_str(&format!(
+                "            f(i, result.expect(\"MockDatabase: no result queued for {method_name}\")?);\n"
+            ));
+            output.push_str("        }\n");
+            output.push_str("        Ok(())\n");
+        } else {
+            output.push_str(&format!(
+                "        self.calls.lock().unwrap().push({call});\n"
+            ));
+            output.push_str(&format!(
+                "        self.{method_name}_results\n            .lock()\n            .unwrap()\n            .pop_front()\n            .expect(\"MockDatabase: no result queued for {method_name}\")\n"
+            ));
+        }
+
+        output.push_str("    }\n");
+        Ok(output)
+    }
+
+    /// Batch methods run one statement for all items, which `batch_sql`
+    /// builds from the query, and report each item's result to `f` in order.
+    /// Each parameter is bound as an array holding every item's value, so the
+    /// whole batch is a single round trip.
+    ///
+    /// Rows carry the `sqlc_ord` of the item they belong to, except for
+    /// `INSERT ... RETURNING`, whose rows come back in item order. When a
+    /// missing row is an error, the statement runs in a transaction so the
+    /// batch is rolled back before `f` sees any result.
+    fn generate_batch_method(&self, query: &Query, receiver: Receiver) -> Result<String> {
+        let mut output = String::new();
+
+        let (_, item_name) = self.get_item_type(query)?;
+        let statement = Self::batch_statement(query)?;
+        let optional = self.returns_option(query);
+        let positional = statement == BatchStatement::Insert && query.cmd != ":batchexec";
+        let needs_tx = statement != BatchStatement::Select
+            && (positional || query.cmd == ":batchone" && !optional);
+
+        output.push_str(&format!(
+            "    pub {} {{\n",
+            self.method_signature(query, receiver, false)?
+        ));
+
+        let mut params: Vec<_> = query.params.iter().zip(Self::param_names(query)).collect();
+        let values = |name: &str| format!("{}_values", ident::unraw(name));
+        for (_, name) in &params {
+            output.push_str(&format!("        let mut {} = Vec::new();\n", values(name)));
+        }
+        output.push_str(&format!("        for {item_name} in items {{\n"));
+        for (_, name) in &params {
+            let value = if self.uses_params_struct(query) {
+                format!("params.{name}")
+            } else {
+                item_name.clone()
+            };
+            output.push_str(&format!("            {}.push({value});\n", values(name)));
+        }
+        output.push_str("        }\n");
+        output.push_str(&format!(
+            "        let count = {}.len();\n",
+            values(&params[0].1)
+        ));
+        output.push_str("        if count == 0 {\n");
+        output.push_str("            return Ok(());\n");
+        output.push_str("        }\n");
+
+        let executor = if needs_tx {
+            if self.with_db_argument() {
+                output.push_str("        let mut tx = sqlx::Connection::begin(db).await?;\n");
+            } else if receiver == Receiver::Transaction {
+                // Nested transactions are savepoints
+                output.push_str(
+                    "        let mut tx = sqlx::Connection::begin(&mut *self.tx).await?;\n",
+                );
+            } else {
+                output.push_str("        let mut tx = self.pool.begin().await?;\n");
+            }
+            "&mut *tx"
+        } else {
+            self.executor(receiver)
+        };
+
+        let sql = Self::sql_const_name(query);
+        if query.cmd == ":batchexec" {
+            output.push_str(&format!("        sqlx::query({sql})\n"));
+        } else {
+            output.push_str(&format!("        let rows = sqlx::query({sql})\n"));
+        }
+        params.sort_by_key(|(param, _)| param.number);
+        for (_, name) in &params {
+            output.push_str(&format!("            .bind({})\n", values(name)));
+        }
+        if query.cmd == ":batchexec" {
+            output.push_str(&format!("            .execute({executor})\n"));
+        } else {
+            output.push_str(&format!("            .fetch_all({executor})\n"));
+        }
+        output.push_str("            .await?;\n");
+
+        let mapping = self.generate_row_mapping(query)?;
+        let mapping = mapping.replace('\n', "\n    ");
+        match query.cmd.as_str() {
+            ":batchexec" => {
+                output.push_str("        for i in 0..count {\n");
+                output.push_str("            f(i, ());\n");
+                output.push_str("        }\n");
+            }
+            _ if positional => {
+                // Rows dropped by a trigger would shift every later item
+                output.push_str("        if rows.len() != count {\n");
+                output.push_str(
+                    "            return Err(SqlxError::Protocol(format!(\"batch inserted {} rows for {count} items\", rows.len())));\n",
+                );
+                output.push_str("        }\n");
+                if needs_tx {
+                    output.push_str("        tx.commit().await?;\n");
+                }
+                output.push_str("        for (i, row) in rows.iter().enumerate() {\n");
+                let result = match query.cmd.as_str() {
+                    ":batchone" if optional => format!("Some({mapping})"),
+                    ":batchone" => mapping,
+                    _ => format!("vec![{mapping}]"),
+                };
+                output.push_str(&format!("            f(i, {result});\n"));
+                output.push_str("        }\n");
+            }
+            cmd => {
+                let empty = if cmd == ":batchone" {
+                    "None"
+                } else {
+                    "Vec::new()"
+                };
+                output.push_str(&format!(
+                    "        let mut results: Vec<_> = (0..count).map(|_| {empty}).collect();\n"
+                ));
+                output.push_str("        for row in &rows {\n");
+                output.push_str(
+                    "            let i = row.try_get::<i64, _>(\"sqlc_ord\")? as usize - 1;\n",
+                );
+                if cmd == ":batchone" {
+                    // Like `fetch_one`, an item matching several rows gets the first
+                    output.push_str("            if results[i].is_none() {\n");
+                    output.push_str(&format!(
+                        "                results[i] = Some({});\n",
+                        mapping.replace('\n', "\n    ")
+                    ));
+                    output.push_str("            }\n");
+                } else {
+                    output.push_str(&format!("            results[i].push({mapping});\n"));
+                }
                 output.push_str("        }\n");
-                output.push_str("        Ok(results)\n");
+                if cmd == ":batchone" && !optional {
+                    output.push_str("        let results = results\n");
+                    output.push_str("            .into_iter()\n");
+                    output.push_str(
+                        "            .map(|result| result.ok_or(SqlxError::RowNotFound))\n",
+                    );
+                    output.push_str("            .collect::<Result<Vec<_>, _>>()?;\n");
+                }
+                if needs_tx {
+                    output.push_str("        tx.commit().await?;\n");
+                }
+                output.push_str("        for (i, result) in results.into_iter().enumerate() {\n");
+                output.push_str("            f(i, result);\n");
+                output.push_str("        }\n");
+            }
+        }
+        output.push_str("        Ok(())\n");
+
+        output.push_str("    }\n");
+        Ok(output)
+    }
+
+    /// `@`-prefixed annotations from the comments above a query, e.g.
+    /// `-- @optional`.
+    fn annotations(query: &Query) -> impl Iterator<Item = &str> {
+        query
+            .comments
+            .iter()
+            .map(|comment| comment.trim())
+            .filter(|comment| comment.starts_with('@'))
+    }
+
+    /// Whether a `:one`/`:batchone` query returns `None` instead of
+    /// `RowNotFound` when no row matches. `@optional` and `@required`
+    /// override the `emit_optional_one` option per query.
+    fn returns_option(&self, query: &Query) -> bool {
+        for annotation in Self::annotations(query) {
+            match annotation {
+                "@optional" => return true,
+                "@required" => return false,
+                _ => {}
+            }
+        }
+        self.options.emit_optional_one.unwrap_or(false)
+    }
+
+    fn get_query_return_type(&self, query: &Query) -> Result<String> {
+        match query.cmd.as_str() {
+            ":one" if self.returns_option(query) => {
+                Ok(format!("Option<{}>", self.get_row_type(query)))
+            }
+            ":one" => Ok(self.get_row_type(query)),
+            ":many" => Ok(format!("Vec<{}>", self.get_row_type(query))),
+            ":exec" => Ok("()".to_string()),
+            ":execrows" => Ok("u64".to_string()),
+            ":execresult" => Ok("PgQueryResult".to_string()),
+            // Batch methods report this type per item
+            ":batchexec" => Ok("()".to_string()),
+            ":batchone" if self.returns_option(query) => {
+                Ok(format!("Option<{}>", self.get_row_type(query)))
+            }
+            ":batchone" => Ok(self.get_row_type(query)),
+            ":batchmany" => Ok(format!("Vec<{}>", self.get_row_type(query))),
+            ":copyfrom" => Ok("u64".to_string()),
+            // Postgres has no last insert id; sqlc-gen-go only supports
+            // this command for MySQL and SQLite
+            ":execlastid" => bail!(
+                "Query {}: :execlastid is not supported by the {} engine, use :one with RETURNING instead",
+                query.name,
+                self.request.settings.engine
+            ),
+            cmd => bail!("Query {}: unknown command '{cmd}'", query.name),
+        }
+    }
+
+    /// The Rust type of a single result row: the column type for one column,
+    /// a table model when the columns match a table exactly, and the
+    /// generated `<QueryName>Row` struct otherwise.
+    fn get_row_type(&self, query: &Query) -> String {
+        match query.columns.as_slice() {
+            [] => "()".to_string(),
+            [col] if col.embed_table.is_none() => {
+                self.column_type(None, col, col.not_null, col.is_array)
+            }
+            _ => match self.find_matching_table(query) {
+                Some(table) => Self::table_struct_name(table),
+                None => Self::row_struct_name(query),
+            },
+        }
+    }
+
+    fn row_struct_name(query: &Query) -> String {
+        ident::pascal_case(&format!("{}_row", query.name))
+    }
+
+    /// Finds a catalog table whose columns are exactly the query's columns,
+    /// in order and with the same Rust types, so its model can be reused.
+    fn find_matching_table(&self, query: &Query) -> Option<&Table> {
+        self.request
+            .catalog
+            .schemas
+            .iter()
+            .flat_map(|schema| &schema.tables)
+            .find(|table| {
+                table.columns.len() == query.columns.len()
+                    && table
+                        .columns
+                        .iter()
+                        .zip(&query.columns)
+                        .all(|(table_col, query_col)| {
+                            query_col.embed_table.is_none()
+                                && table_col.name == query_col.name
+                                && self.column_type(
+                                    Some(&table.rel),
+                                    table_col,
+                                    table_col.not_null,
+                                    table_col.is_array,
+                                ) == self.column_type(
+                                    None,
+                                    query_col,
+                                    query_col.not_null,
+                                    query_col.is_array,
+                                )
+                        })
+            })
+    }
+
+    /// Whether a query returning rows needs its own row struct, because it
+    /// returns several columns that don't match a table.
+    fn needs_row_struct(&self, query: &Query) -> bool {
+        matches!(
+            query.cmd.as_str(),
+            ":one" | ":many" | ":batchone" | ":batchmany"
+        ) && !matches!(
+            query.columns.as_slice(),
+            [] | [Column {
+                embed_table: None,
+                ..
+            }]
+        ) && self.find_matching_table(query).is_none()
+    }
+
+    fn generate_row_struct(&self, query: &Query) -> Result<Option<String>> {
+        if !self.needs_row_struct(query) {
+            return Ok(None);
+        }
+
+        self.generate_struct(&Self::row_struct_name(query), None, None, &query.columns)
+            .map(Some)
+    }
+
+    /// Like [`Self::generate_row_mapping`], but a `Result` of the row type
+    /// rather than a row that may return early with `?`.
+    fn generate_row_result(&self, query: &Query) -> Result<String> {
+        match query.columns.as_slice() {
+            [col] if col.embed_table.is_none() => {
+                let rust_type = self.column_type(None, col, col.not_null, col.is_array);
+                Ok(format!("row.try_get::<{rust_type}, _>(0)"))
             }
-            ":exec" => {
-                output.push_str("        sqlx::query(query)\n");
-                for (i, _) in query.params.iter().enumerate() {
-                    output.push_str(&format!("            .bind(param_{})\n", i + 1));
+            _ => Ok(format!("Ok({})", self.generate_row_mapping(query)?)),
+        }
+    }
+
+    /// Expression building the row type from a `PgRow` bound to `row`. It
+    /// uses `?` to return a column that fails to decode as the method's
+    /// error, so it must be placed where `?` returns a `SqlxError`.
+    fn generate_row_mapping(&self, query: &Query) -> Result<String> {
+        match query.columns.as_slice() {
+            [] => Ok("()".to_string()),
+            [col] if col.embed_table.is_none() => {
+                let rust_type = self.column_type(None, col, col.not_null, col.is_array);
+                Ok(format!("row.try_get::<{rust_type}, _>(0)?"))
+            }
+            columns => {
+                let mut mapping = format!("{} {{\n", self.get_row_type(query));
+                // Embedded tables span one result column per table column
+                let mut index = 0;
+                for (col, field_name) in columns.iter().zip(Self::field_names(columns, "column")) {
+                    if col.embed_table.is_none() {
+                        let rust_type = self.column_type(None, col, col.not_null, col.is_array);
+                        mapping.push_str(&format!(
+                            "            {field_name}: row.try_get::<{rust_type}, _>({index})?,\n"
+                        ));
+                        index += 1;
+                        continue;
+                    }
+
+                    let table = self.embedded_table(col)?;
+                    mapping.push_str(&format!(
+                        "            {field_name}: {} {{\n",
+                        Self::table_struct_name(table)
+                    ));
+                    for (table_col, table_field) in table
+                        .columns
+                        .iter()
+                        .zip(Self::field_names(&table.columns, "column"))
+                    {
+                        let rust_type = self.column_type(
+                            Some(&table.rel),
+                            table_col,
+                            table_col.not_null,
+                            table_col.is_array,
+                        );
+                        mapping.push_str(&format!(
+                            "                {table_field}: row.try_get::<{rust_type}, _>({index})?,\n"
+                        ));
+                        index += 1;
+                    }
+                    mapping.push_str("            },\n");
                 }
-                output.push_str("            .execute(&self.pool)\n");
-                output.push_str("            .await?;\n\n");
-                output.push_str("        Ok(())\n");
+                mapping.push_str("        }");
+                Ok(mapping)
             }
-            _ => {
-                output.push_str("        // Unknown command type\n");
-                output.push_str("        Ok(())\n");
+        }
+    }
+
+    /// The catalog table a `sqlc.embed()` column expands to.
+    fn embedded_table(&self, column: &Column) -> Result<&Table> {
+        let Some(embed) = &column.embed_table else {
+            bail!("Column {} is not an embedded table", column.name);
+        };
+        let schema = if embed.schema.is_empty() {
+            &self.request.catalog.default_schema
+        } else {
+            &embed.schema
+        };
+
+        self.request
+            .catalog
+            .schemas
+            .iter()
+            .filter(|s| &s.name == schema)
+            .flat_map(|s| &s.tables)
+            .find(|table| table.rel.name == embed.name)
+            .with_context(|| format!("sqlc.embed({}): table not found in catalog", embed.name))
+    }
+
+    /// The `use` block at the top of a generated file: the common Rust
+    /// imports, those required by `overrides`, optionally the sqlx ones, and
+    /// then the file's own `extra` lines.
+    fn generate_imports(&self, with_sqlx: bool, extra: &[String]) -> String {
+        let mut output = String::new();
+        for import in TypeMapper::get_rust_imports() {
+            output.push_str(import);
+            output.push('\n');
+        }
+        for import in self.override_imports() {
+            output.push_str(&import);
+            output.push('\n');
+        }
+        if with_sqlx {
+            for import in TypeMapper::get_sqlx_imports() {
+                output.push_str(import);
+                output.push('\n');
             }
         }
+        for import in extra {
+            output.push_str(import);
+            output.push('\n');
+        }
+        output
+    }
 
-        output.push_str("    }\n");
-        Ok(output)
+    /// `use crate::<module>::*;` for the module generated into `file_name`.
+    fn crate_glob_import(file_name: &str) -> String {
+        format!("use crate::{}::*;", Self::module_name(file_name))
     }
 
-    fn get_query_return_type(&self, query: &Query) -> Result<String> {
-        match query.cmd.as_str() {
-            ":one" => {
-                if query.columns.len() == 1 {
-                    let col = &query.columns[0];
-                    Ok(TypeMapper::sql_to_rust_type(
-                        &col.r#type,
-                        col.not_null,
-                        col.is_array,
-                    ))
-                } else if query.columns.is_empty() {
-                    Ok("()".to_string())
-                } else {
-                    // For multiple columns, use a tuple type
-                    let types: Vec<String> = query
+    /// `use` declarations for the paths listed by `overrides`, once each and
+    /// skipping those every file already has.
+    fn override_imports(&self) -> Vec<String> {
+        let mut imports: Vec<String> = Vec::new();
+        for path in self.type_mapper.overrides().iter().flat_map(|o| &o.imports) {
+            let import = format!(
+                "use {};",
+                path.trim().trim_start_matches("use ").trim_end_matches(';')
+            );
+            if !imports.contains(&import)
+                && !TypeMapper::get_rust_imports().contains(&import.as_str())
+            {
+                imports.push(import);
+            }
+        }
+        imports
+    }
+
+    /// The Rust type of a column, applying the `overrides` option. `table`
+    /// is the table of catalog columns.
+    fn column_type(
+        &self,
+        table: Option<&Identifier>,
+        column: &Column,
+        not_null: bool,
+        is_array: bool,
+    ) -> String {
+        self.type_mapper
+            .column_type(table, column, not_null, is_array)
+    }
+
+    /// The owned Rust type of a parameter, as used in params structs, batch
+    /// items and mock calls. `sqlc.slice()` parameters become a `Vec`.
+    fn param_type(&self, query: &Query, param: &Parameter) -> String {
+        if param.column.is_sqlc_slice {
+            return format!("Vec<{}>", self.slice_element_type(param));
+        }
+        self.column_type(
+            None,
+            &param.column,
+            Self::param_not_null(query, param),
+            param.column.is_array,
+        )
+    }
+
+    /// Whether a parameter is required. sqlc reports this per parameter, e.g.
+    /// `sqlc.narg()` is nullable, and `@param <name> optional|required`
+    /// overrides it per query. Parameters are named as in the query or by
+    /// placeholder, e.g. `$2`.
+    fn param_not_null(query: &Query, param: &Parameter) -> bool {
+        Self::param_annotations(query)
+            .filter_map(|annotation| annotation.ok())
+            .filter(|(name, _)| Self::param_matches(param, name))
+            .map(|(_, not_null)| not_null)
+            .last()
+            .unwrap_or(param.column.not_null)
+    }
+
+    /// `@param <name> optional|required` annotations, as the parameter name
+    /// and whether it is required.
+    fn param_annotations(query: &Query) -> impl Iterator<Item = Result<(&str, bool)>> + '_ {
+        Self::annotations(query)
+            .filter_map(|annotation| annotation.strip_prefix("@param "))
+            .map(|annotation| match annotation.split_whitespace().collect::<Vec<_>>()[..] {
+                [name, "optional"] => Ok((name, false)),
+                [name, "required"] => Ok((name, true)),
+                _ => bail!(
+                    "invalid annotation '@param {annotation}', expected '@param <name> optional|required'"
+                ),
+            })
+    }
+
+    fn param_matches(param: &Parameter, name: &str) -> bool {
+        name == param.column.name || name == format!("${}", param.number)
+    }
+
+    /// Checks that a query's parameters are numbered `$1` to `$n` without
+    /// gaps or duplicates, so every placeholder gets exactly one bind.
+    fn check_param_numbers(query: &Query) -> Result<()> {
+        let mut numbers: Vec<i32> = query.params.iter().map(|param| param.number).collect();
+        numbers.sort_unstable();
+
+        for (expected, number) in (1..).zip(numbers) {
+            if number < 1 {
+                bail!("Query {}: invalid parameter number {number}", query.name);
+            }
+            if number < expected {
+                bail!(
+                    "Query {}: parameter ${number} is reported more than once",
+                    query.name
+                );
+            }
+            if number > expected {
+                bail!(
+                    "Query {}: parameter ${expected} is missing, parameters must be numbered without gaps",
+                    query.name
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn check_param_annotations(query: &Query) -> Result<()> {
+        for annotation in Self::param_annotations(query) {
+            let (name, _) = annotation.map_err(|err| anyhow!("Query {}: {err}", query.name))?;
+            if !query
+                .params
+                .iter()
+                .any(|param| Self::param_matches(param, name))
+            {
+                bail!("Query {}: @param {name} matches no parameter", query.name);
+            }
+        }
+        Ok(())
+    }
+
+    /// Rejects multirange columns and parameters without an override, which
+    /// would compile but fail to decode or encode at runtime.
+    fn check_multiranges(&self, query: &Query) -> Result<()> {
+        let mut columns: Vec<(&str, Option<&Identifier>, &Column)> = Vec::new();
+        for column in &query.columns {
+            if column.embed_table.is_some() {
+                let table = self.embedded_table(column)?;
+                columns.extend(
+                    table
                         .columns
                         .iter()
-                        .map(|col| {
-                            TypeMapper::sql_to_rust_type(&col.r#type, col.not_null, col.is_array)
-                        })
-                        .collect();
-                    Ok(format!("({})", types.join(", ")))
+                        .map(|c| ("column", Some(&table.rel), c)),
+                );
+            } else {
+                columns.push(("column", None, column));
+            }
+        }
+        columns.extend(query.params.iter().map(|p| ("parameter", None, &p.column)));
+
+        for (kind, table, column) in columns {
+            if let Some(sql_type) = self.type_mapper.unmapped_multirange(table, column) {
+                bail!(
+                    "Query {}: {kind} {} has type {sql_type}, which sqlx can't decode or encode; \
+                     convert it from or to an array of ranges in SQL, or map it with an override",
+                    query.name,
+                    column.name
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Rejects `:copyfrom` parameters whose binary encoding would carry a
+    /// type OID that sqlx only resolves when binding, see
+    /// `TypeMapper::unresolved_copy_oid`, so that rows never go out with the
+    /// OID left as 0.
+    fn check_copy_types(&self, query: &Query) -> Result<()> {
+        if query.cmd != ":copyfrom" {
+            return Ok(());
+        }
+        for param in &query.params {
+            if let Some(sql_type) = self.type_mapper.unresolved_copy_oid(None, &param.column) {
+                bail!(
+                    "Query {}: parameter {} has type {sql_type}, which :copyfrom can't encode \
+                     since sqlx leaves the OIDs of enum and composite types in its values unset; \
+                     use :exec or a batch instead",
+                    query.name,
+                    param.column.name
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Element type of a `sqlc.slice()` parameter. `NULL` never matches an
+    /// `IN` list, so elements aren't optional.
+    fn slice_element_type(&self, param: &Parameter) -> String {
+        self.column_type(None, &param.column, true, false)
+    }
+
+    /// Name of the `pub const` holding a query's SQL.
+    fn sql_const_name(query: &Query) -> String {
+        ident::shouty_snake_case(&query.name)
+    }
+
+    /// `pub const` items holding the SQL of every query, which the methods
+    /// reference and callers can reuse.
+    fn generate_sql_consts(&self) -> Result<String> {
+        let mut output = String::new();
+        for query in &self.request.queries {
+            let mut sql = Self::query_sql(query)?;
+            if self.options.normalize_sql_whitespace.unwrap_or(false) {
+                sql = sql::normalize_whitespace(&sql);
+            }
+            output.push_str(&format!(
+                "pub const {}: &str = {};\n\n",
+                Self::sql_const_name(query),
+                sql::string_literal(&sql)
+            ));
+        }
+        Ok(output)
+    }
+
+    /// The SQL a query's method runs.
+    fn query_sql(query: &Query) -> Result<String> {
+        if Self::is_batch(query) {
+            return Self::batch_sql(query);
+        }
+        if query.cmd != ":copyfrom" {
+            return Self::rewrite_slices(query);
+        }
+
+        let table = match &query.insert_into_table {
+            Some(table) if table.schema.is_empty() => format!("\"{}\"", table.name),
+            Some(table) => format!("\"{}\".\"{}\"", table.schema, table.name),
+            None => bail!(
+                "Query {}: :copyfrom requires an INSERT INTO statement",
+                query.name
+            ),
+        };
+        let columns = query
+            .params
+            .iter()
+            .map(|param| format!("\"{}\"", param.column.name))
+            .collect::<Vec<_>>()
+            .join(", ");
+        Ok(format!(
+            "COPY {table} ({columns}) FROM STDIN (FORMAT binary)"
+        ))
+    }
+
+    /// The kind of statement a batch query runs, from its first top-level
+    /// keyword after any `WITH` clause.
+    fn batch_statement(query: &Query) -> Result<BatchStatement> {
+        let tokens = sql::tokens(&query.text);
+        // MERGE is found so that its `THEN DELETE` isn't taken for a DELETE
+        let keyword = tokens.iter().filter(|t| t.depth == 0).find_map(|t| {
+            match t.text.to_ascii_uppercase().as_str() {
+                "SELECT" | "VALUES" | "TABLE" => Some(Some(BatchStatement::Select)),
+                "INSERT" => Some(Some(BatchStatement::Insert)),
+                "UPDATE" => Some(Some(BatchStatement::Update)),
+                "DELETE" => Some(Some(BatchStatement::Delete)),
+                "MERGE" => Some(None),
+                _ => None,
+            }
+        });
+        match (keyword, tokens.first()) {
+            (Some(Some(statement)), _) => Ok(statement),
+            // A parenthesized set operation, e.g. `(SELECT ...) UNION (...)`
+            (None, Some(token)) if token.text == "(" => Ok(BatchStatement::Select),
+            _ => bail!(
+                "Query {}: {} queries must be a SELECT, INSERT, UPDATE or DELETE",
+                query.name,
+                query.cmd
+            ),
+        }
+    }
+
+    /// A batch query rewritten to run every item in one statement. Each
+    /// parameter is bound as an array of the items' values, `unnest` turns
+    /// the arrays back into one `sqlc_batch` row per item, and every `$n`
+    /// becomes that row's `sqlc_pn` column. `sqlc_ord` numbers the items
+    /// from 1 so returned rows can be matched to them.
+    fn batch_sql(query: &Query) -> Result<String> {
+        for param in &query.params {
+            if param.column.is_sqlc_slice || param.column.is_array || param.column.array_dims > 0 {
+                bail!(
+                    "Query {}: {} parameter {} can't be an array, because each parameter is bound as an array of the items' values",
+                    query.name,
+                    query.cmd,
+                    param.column.name
+                );
+            }
+        }
+
+        let text = query.text.trim().trim_end_matches(';');
+        let mut tokens = sql::tokens(text);
+        // Anything appended after a trailing line comment needs a new line
+        let separator = match tokens.last() {
+            Some(token) if token.text.starts_with("--") => "\n",
+            _ => "",
+        };
+        tokens.retain(|t| !t.is_comment());
+        let statement = Self::batch_statement(query)?;
+        let returns_rows = query.cmd != ":batchexec";
+
+        let mut params: Vec<_> = query.params.iter().collect();
+        params.sort_by_key(|param| param.number);
+        let arrays = params
+            .iter()
+            .map(|param| format!("${}{}", param.number, Self::array_cast(&param.column)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let columns = params
+            .iter()
+            .map(|param| format!("sqlc_p{}", param.number))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let batch = format!("unnest({arrays}) WITH ORDINALITY AS sqlc_batch ({columns}, sqlc_ord)");
+
+        let top_level = |from: usize, words: &[&str]| {
+            tokens[from..]
+                .iter()
+                .position(|t| t.depth == 0 && words.iter().any(|word| t.is_word(word)))
+                .map(|i| from + i)
+        };
+        let keyword = match statement {
+            BatchStatement::Select => None,
+            BatchStatement::Insert => top_level(0, &["INSERT"]),
+            BatchStatement::Update => top_level(0, &["UPDATE"]),
+            BatchStatement::Delete => top_level(0, &["DELETE"]),
+        };
+        // Only the main statement can see `sqlc_batch`
+        if let Some(keyword) = keyword {
+            if tokens[..keyword].iter().any(|t| t.placeholder().is_some()) {
+                bail!(
+                    "Query {}: {} queries can't use parameters in the WITH clause of an INSERT, UPDATE or DELETE",
+                    query.name,
+                    query.cmd
+                );
+            }
+        }
+
+        match statement {
+            BatchStatement::Select => {
+                // A subquery can't hold a data-modifying WITH clause
+                let modifies = tokens.windows(2).any(|pair| {
+                    pair[0].text == "("
+                        && ["INSERT", "UPDATE", "DELETE"]
+                            .iter()
+                            .any(|word| pair[1].is_word(word))
+                });
+                if modifies {
+                    bail!(
+                        "Query {}: {} queries can't use INSERT, UPDATE or DELETE in a WITH clause",
+                        query.name,
+                        query.cmd
+                    );
                 }
+                // `sqlc_row` keeps each item's rows in the query's order
+                Ok(format!(
+                    "SELECT sqlc_q.*, sqlc_batch.sqlc_ord\nFROM {batch}\nCROSS JOIN LATERAL (\nSELECT sqlc_inner.*, row_number() OVER () AS sqlc_row\nFROM (\n{}\n) AS sqlc_inner\n) AS sqlc_q\nORDER BY sqlc_batch.sqlc_ord, sqlc_q.sqlc_row",
+                    Self::batch_columns(text, &tokens, 0..text.len())
+                ))
             }
-            ":many" => {
-                let inner_type = if query.columns.len() == 1 {
-                    let col = &query.columns[0];
-                    TypeMapper::sql_to_rust_type(&col.r#type, col.not_null, col.is_array)
-                } else if query.columns.is_empty() {
-                    "()".to_string()
+            BatchStatement::Update | BatchStatement::Delete => {
+                let keyword = keyword.unwrap_or_default();
+                let (from, join) = if statement == BatchStatement::Update {
+                    ("FROM", top_level(keyword + 1, &["FROM"]))
                 } else {
-                    let types: Vec<String> = query
-                        .columns
+                    ("USING", top_level(keyword + 1, &["USING"]))
+                };
+                // After any other FROM items, so `RETURNING *` keeps its columns
+                let at = top_level(keyword + 1, &["WHERE", "RETURNING"])
+                    .map_or(text.len(), |i| tokens[i].offset);
+                let mut sql = Self::batch_columns(text, &tokens, 0..at)
+                    .trim_end()
+                    .to_string();
+                match join {
+                    Some(_) => sql.push_str(&format!(",\n{batch}")),
+                    None => sql.push_str(&format!("\n{from} {batch}")),
+                }
+                if at < text.len() {
+                    sql.push('\n');
+                    sql.push_str(&Self::batch_columns(text, &tokens, at..text.len()));
+                }
+                if returns_rows {
+                    match top_level(keyword + 1, &["RETURNING"]) {
+                        Some(_) => sql.push_str(&format!("{separator}, sqlc_batch.sqlc_ord")),
+                        None => sql.push_str("\nRETURNING sqlc_batch.sqlc_ord"),
+                    }
+                }
+                Ok(sql)
+            }
+            BatchStatement::Insert => {
+                let keyword = keyword.unwrap_or_default();
+                let Some(start) = top_level(keyword + 1, &["VALUES", "SELECT", "WITH", "TABLE"])
+                else {
+                    bail!(
+                        "Query {}: {} INSERT queries must insert VALUES or a SELECT",
+                        query.name,
+                        query.cmd
+                    );
+                };
+                let end = (start + 1..tokens.len())
+                    .find(|&i| {
+                        tokens[i].depth == 0
+                            && (tokens[i].is_word("RETURNING")
+                                || tokens[i].is_word("ON")
+                                    && tokens.get(i + 1).is_some_and(|t| t.is_word("CONFLICT")))
+                    })
+                    .unwrap_or(tokens.len());
+                let source = &tokens[start..end];
+                if source.iter().any(|t| t.is_word("DEFAULT")) {
+                    bail!(
+                        "Query {}: {} INSERT queries can't use DEFAULT, because the inserted rows become a SELECT",
+                        query.name,
+                        query.cmd
+                    );
+                }
+                if tokens[end..].iter().any(|t| t.placeholder().is_some()) {
+                    bail!(
+                        "Query {}: {} INSERT queries can only use parameters in the inserted rows",
+                        query.name,
+                        query.cmd
+                    );
+                }
+
+                // `VALUES (...)` for one row per item, as its expressions
+                let single_row = match source {
+                    [values, open, .., close] if values.is_word("VALUES") && open.text == "(" => {
+                        close.text == ")" && source[2..source.len() - 1].iter().all(|t| t.depth > 0)
+                    }
+                    _ => false,
+                };
+                if returns_rows {
+                    // Rows are matched to items by position
+                    let conflict = &tokens[end..];
+                    let skips_rows = conflict
                         .iter()
-                        .map(|col| {
-                            TypeMapper::sql_to_rust_type(&col.r#type, col.not_null, col.is_array)
-                        })
-                        .collect();
-                    format!("({})", types.join(", "))
+                        .any(|t| t.depth == 0 && t.is_word("NOTHING"))
+                        || conflict
+                            .iter()
+                            .skip_while(|t| !(t.depth == 0 && t.is_word("DO")))
+                            .any(|t| t.depth == 0 && t.is_word("WHERE"));
+                    if !single_row || skips_rows {
+                        bail!(
+                            "Query {}: {} INSERT queries must insert one VALUES row per item and can't skip rows with ON CONFLICT, because rows are matched to items by position",
+                            query.name,
+                            query.cmd
+                        );
+                    }
+                }
+
+                let source_start = tokens[start].offset;
+                let source_end = tokens.get(end).map_or(text.len(), |t| t.offset);
+                let rows = if single_row {
+                    let open = source[1].range().end;
+                    let close = source[source.len() - 1].offset;
+                    format!(
+                        "SELECT {}\nFROM {batch}\nORDER BY sqlc_batch.sqlc_ord",
+                        Self::batch_columns(text, &tokens, open..close).trim()
+                    )
+                } else {
+                    format!(
+                        "SELECT sqlc_q.*\nFROM {batch}\nCROSS JOIN LATERAL (\n{}\n) AS sqlc_q\nORDER BY sqlc_batch.sqlc_ord",
+                        Self::batch_columns(text, &tokens, source_start..source_end).trim_end()
+                    )
                 };
-                Ok(format!("Vec<{inner_type}>"))
+                Ok(
+                    format!("{}{rows}\n{}", &text[..source_start], &text[source_end..])
+                        .trim_end()
+                        .to_string(),
+                )
             }
-            ":exec" => Ok("()".to_string()),
-            _ => Ok("()".to_string()),
         }
     }
 
-    fn generate_row_mapping(&self, query: &Query) -> Result<String> {
-        if query.columns.len() == 1 {
-            let col = &query.columns[0];
-            let rust_type = TypeMapper::sql_to_rust_type(&col.r#type, col.not_null, col.is_array);
-            Ok(format!("        Ok(row.get::<{rust_type}, _>(0))\n"))
-        } else if query.columns.len() > 1 {
-            // For multiple columns, create a tuple or struct-like mapping
-            let mut mapping = String::new();
-            mapping.push_str("        Ok((\n");
-            for (i, col) in query.columns.iter().enumerate() {
-                let rust_type =
-                    TypeMapper::sql_to_rust_type(&col.r#type, col.not_null, col.is_array);
-                mapping.push_str(&format!("             row.get::<{rust_type}, _>({i}),\n"));
-            }
-            mapping.push_str("        ))\n");
-            Ok(mapping)
+    /// `text[range]` with every `$n` replaced by the item's `sqlc_pn` column.
+    fn batch_columns(text: &str, tokens: &[sql::Token], range: Range<usize>) -> String {
+        let mut output = String::new();
+        let mut last = range.start;
+        for token in tokens.iter().filter(|t| range.contains(&t.offset)) {
+            if let Some(number) = token.placeholder() {
+                output.push_str(&text[last..token.offset]);
+                output.push_str(&format!("sqlc_batch.sqlc_p{number}"));
+                last = token.range().end;
+            }
+        }
+        output.push_str(&text[last..range.end]);
+        output
+    }
+
+    /// The `::type[]` cast of a batch parameter's array, so the statement can
+    /// be prepared without the bound types and `unnest` knows its columns.
+    fn array_cast(column: &Column) -> String {
+        let Some(ty) = &column.r#type else {
+            return String::new();
+        };
+        // Serial types only exist in CREATE TABLE
+        let name = match ty.name.as_str() {
+            "" | "any" => return String::new(),
+            "serial" | "serial4" => "int4",
+            "bigserial" | "serial8" => "int8",
+            "smallserial" | "serial2" => "int2",
+            name => name,
+        };
+        let builtin = ty.schema.is_empty() || ty.schema == "pg_catalog";
+        if builtin
+            && name
+                .bytes()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || b"_ ".contains(&c))
+        {
+            format!("::{name}[]")
+        } else if ty.schema.is_empty() {
+            format!("::\"{name}\"[]")
+        } else {
+            format!("::\"{}\".\"{name}\"[]", ty.schema)
+        }
+    }
+
+    /// Query text with every `IN (sqlc.slice('x'))` rewritten to
+    /// `= ANY($n)`, and `NOT IN` to `<> ALL($n)`, so the slice binds as a
+    /// single array parameter and the statement text doesn't depend on its
+    /// length.
+    fn rewrite_slices(query: &Query) -> Result<String> {
+        let mut sql = query.text.clone();
+        for param in query.params.iter().filter(|p| p.column.is_sqlc_slice) {
+            let mut rewritten = false;
+            while let Some(next) = Self::rewrite_slice(&sql, param) {
+                sql = next;
+                rewritten = true;
+            }
+            if !rewritten {
+                bail!(
+                    "Query {}: sqlc.slice('{}') must be the only element of an IN list",
+                    query.name,
+                    param.column.name
+                );
+            }
+        }
+        Ok(sql)
+    }
+
+    /// Rewrites the first `IN` list holding a slice parameter's placeholder,
+    /// which is `$n` on PostgreSQL and `/*SLICE:name*/?` on other engines.
+    fn rewrite_slice(sql: &str, param: &Parameter) -> Option<String> {
+        let number = format!("${}", param.number);
+        let placeholders = [
+            format!("/*SLICE:{}*/?", param.column.name),
+            format!("/*SLICE:{}*/{number}", param.column.name),
+            number,
+        ];
+
+        for placeholder in &placeholders {
+            for (start, _) in sql.match_indices(placeholder.as_str()) {
+                let end = start + placeholder.len();
+                // `$1` must not match the start of `$10`
+                if sql[end..].starts_with(|c: char| c.is_ascii_digit()) {
+                    continue;
+                }
+                if let Some((from, to, negated)) = Self::in_list_around(sql, start, end) {
+                    let operator = if negated { "<> ALL" } else { "= ANY" };
+                    return Some(format!(
+                        "{}{operator}(${}){}",
+                        &sql[..from],
+                        param.number,
+                        &sql[to..]
+                    ));
+                }
+            }
+        }
+        None
+    }
+
+    /// The byte range of `[NOT] IN (placeholder)` around the placeholder at
+    /// `start..end`, and whether it is negated.
+    fn in_list_around(sql: &str, start: usize, end: usize) -> Option<(usize, usize, bool)> {
+        let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+
+        let before = sql[..start].trim_end().strip_suffix('(')?.trim_end();
+        if !before.to_ascii_uppercase().ends_with("IN") {
+            return None;
+        }
+        let mut from = before.len() - 2;
+        if before[..from].ends_with(is_ident) {
+            return None;
+        }
+        let prefix = before[..from].trim_end();
+        let negated = prefix.to_ascii_uppercase().ends_with("NOT")
+            && !prefix[..prefix.len() - 3].ends_with(is_ident);
+        if negated {
+            from = prefix.len() - 3;
+        }
+
+        let rest = sql[end..].trim_start().strip_prefix(')')?;
+        Some((from, sql.len() - rest.len(), negated))
+    }
+
+    fn params_struct_name(query: &Query) -> String {
+        ident::pascal_case(&format!("{}_params", query.name))
+    }
+
+    fn param_names(query: &Query) -> Vec<String> {
+        Self::field_names(query.params.iter().map(|param| &param.column), "param")
+    }
+
+    /// Parameter names for positional arguments, renamed where they would
+    /// shadow the locals used in the generated method body.
+    fn argument_names(query: &Query) -> Vec<String> {
+        Self::param_names(query)
+            .into_iter()
+            .map(|name| match name.as_str() {
+                "query" | "row" | "rows" | "result" | "params" | "tx" | "items" | "i" | "f"
+                | "conn" | "copy" | "buf" | "db" => {
+                    format!("{name}_param")
+                }
+                _ => name,
+            })
+            .collect()
+    }
+
+    /// Whether the query takes a single params struct instead of positional
+    /// arguments. A negative `query_parameter_limit` never uses one, a limit
+    /// of zero always does. Batches and copies with more than one parameter
+    /// always use one as their item type.
+    fn uses_params_struct(&self, query: &Query) -> bool {
+        // Batch and copy items carry all parameters of one row
+        if Self::takes_items(query) && query.params.len() > 1 {
+            return true;
+        }
+
+        match self.options.query_parameter_limit.unwrap_or(1) {
+            limit if limit >= 0 => query.params.len() > limit as usize,
+            _ => false,
+        }
+    }
+
+    fn generate_params_struct(&self, query: &Query) -> Result<Option<String>> {
+        if !self.uses_params_struct(query) {
+            return Ok(None);
+        }
+
+        let mut output = String::new();
+        output.push_str("#[derive(Debug, Clone)]\n");
+        output.push_str(&format!(
+            "pub struct {} {{\n",
+            Self::params_struct_name(query)
+        ));
+
+        for (param, field_name) in query.params.iter().zip(Self::param_names(query)) {
+            let field_type = self.param_type(query, param);
+            output.push_str(&format!("    pub {field_name}: {field_type},\n"));
+        }
+
+        output.push_str("}\n");
+        Ok(Some(output))
+    }
+
+    /// The builder calls chained onto `sqlx::query(SQL)`, one bind per
+    /// parameter.
+    ///
+    /// Binds are positional, so they follow the parameters' `$n` numbers
+    /// rather than the order sqlc lists them in.
+    fn generate_binds(&self, query: &Query) -> String {
+        let args: Vec<String> = if self.uses_params_struct(query) {
+            Self::param_names(query)
+                .into_iter()
+                .map(|name| format!("params.{name}"))
+                .collect()
         } else {
-            Ok("        Ok(())\n".to_string())
+            Self::argument_names(query)
+        };
+        let mut args: Vec<_> = query.params.iter().zip(args).collect();
+        args.sort_by_key(|(param, _)| param.number);
+
+        let mut output = String::new();
+        for (_, arg) in args {
+            output.push_str(&format!("            .bind({arg})\n"));
         }
+        output
     }
 
     fn generate_lib(&self) -> Result<String> {
         let mut output = String::new();
+        let models = Self::module_name(&self.models_file_name());
+        let queries = Self::module_name(&self.db_file_name());
 
-        output.push_str("pub mod models;\n");
-        output.push_str("pub mod queries;\n\n");
-        output.push_str("pub use models::*;\n");
-        output.push_str("pub use queries::Database;\n");
+        output.push_str(&format!("pub mod {models};\n"));
+        output.push_str(&format!("pub mod {queries};\n"));
+        if self.has_batch_queries() {
+            output.push_str(&format!(
+                "pub mod {};\n",
+                Self::module_name(&self.batch_file_name())
+            ));
+        }
+        let querier = Self::module_name(&self.querier_file_name());
+        if self.emits_interface() {
+            output.push_str(&format!("pub mod {querier};\n"));
+        }
+        if self.emits_mock() {
+            output.push_str(&format!(
+                "#[cfg({})]\npub mod {};\n",
+                self.mock_cfg(),
+                Self::module_name(&self.mock_file_name())
+            ));
+        }
+        output.push('\n');
+        output.push_str(&format!("pub use {models}::*;\n"));
+        if self.emits_transactions() {
+            output.push_str(&format!(
+                "pub use {queries}::{{Database, DatabaseTx, IsolationLevel, TransactionOptions}};\n"
+            ));
+        } else {
+            output.push_str(&format!("pub use {queries}::Database;\n"));
+        }
+        if self.emits_interface() {
+            output.push_str(&format!("pub use {querier}::Querier;\n"));
+        }
 
         Ok(output)
     }
@@ -353,19 +2951,441 @@ impl Default for PluginOptions {
             emit_methods_with_db_argument: Some(false),
             emit_enum_valid_method: Some(false),
             emit_all_enum_values: Some(false),
+            emit_optional_one: Some(false),
+            emit_stream_methods: Some(false),
+            emit_mock: Some(false),
+            normalize_sql_whitespace: Some(false),
+            emit_domain_newtypes: Some(false),
             json_tags_case_style: Some("snake_case".to_string()),
             output_batch_file_name: None,
             output_db_file_name: Some("queries.rs".to_string()),
             output_models_file_name: Some("models.rs".to_string()),
             output_querier_file_name: None,
+            output_mock_file_name: None,
+            mock_cfg: None,
             output_files_suffix: None,
             inflection_exclude_table_names: None,
-            query_parameter_limit: None,
+            query_parameter_limit: Some(1),
             omit_unused_structs: Some(false),
             omit_sqlc_version: Some(false),
             build_tags: None,
             sql_package: Some("sqlx".to_string()),
             sql_driver: Some("postgres".to_string()),
+            overrides: None,
+            composite_types: None,
+            domains: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(name: &str, sql_type: &str, is_sqlc_slice: bool) -> serde_json::Value {
+        serde_json::json!({
+            "name": name,
+            "not_null": true,
+            "is_array": false,
+            "comment": null,
+            "length": null,
+            "is_named_param": false,
+            "is_func_call": false,
+            "scope": null,
+            "table": null,
+            "table_alias": null,
+            "type": { "catalog": "", "schema": "pg_catalog", "name": sql_type },
+            "is_sqlc_slice": is_sqlc_slice,
+            "embed_table": null
+        })
+    }
+
+    fn slice_param(number: i32, name: &str) -> Parameter {
+        serde_json::from_value(serde_json::json!({
+            "number": number,
+            "column": column(name, "int4", true)
+        }))
+        .unwrap()
+    }
+
+    fn multirange_query(cmd: &str) -> Query {
+        let (columns, params) = match cmd {
+            ":many" => (vec![column("free", "datemultirange", false)], vec![]),
+            _ => (
+                vec![],
+                vec![serde_json::json!({
+                    "number": 1,
+                    "column": column("free", "datemultirange", false)
+                })],
+            ),
+        };
+        serde_json::from_value(serde_json::json!({
+            "text": "",
+            "name": "Free",
+            "cmd": cmd,
+            "columns": columns,
+            "params": params,
+            "comments": [],
+            "filename": "queries.sql"
+        }))
+        .unwrap()
+    }
+
+    /// A request without queries. `plugin_options` are encoded the way sqlc
+    /// sends them, as base64 `bytes`, with `null` standing for none.
+    fn request(plugin_options: serde_json::Value) -> GenerateRequest {
+        use base64::Engine;
+
+        let plugin_options = match plugin_options {
+            serde_json::Value::Null => String::new(),
+            options => base64::engine::general_purpose::STANDARD.encode(options.to_string()),
+        };
+        serde_json::from_value(serde_json::json!({
+            "settings": {
+                "version": "2",
+                "engine": "postgresql",
+                "schema": [],
+                "queries": [],
+                "codegen": null
+            },
+            "catalog": { "comment": "", "default_schema": "public", "name": "", "schemas": [] },
+            "queries": [],
+            "sqlc_version": "v1.27.0",
+            "plugin_options": plugin_options,
+            "global_options": ""
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn rejects_invalid_plugin_options() {
+        let err = RustGenerator::new(request(serde_json::json!({
+            "overrides": [{ "db_type": "int4" }]
+        })))
+        .err()
+        .unwrap();
+        assert_eq!(
+            format!("{err:#}"),
+            "Failed to parse plugin options: missing field `rust_type`"
+        );
+    }
+
+    #[test]
+    fn defaults_missing_plugin_options() {
+        assert!(RustGenerator::new(request(serde_json::Value::Null)).is_ok());
+    }
+
+    #[test]
+    fn rejects_multirange_columns_and_params() {
+        for (cmd, kind) in [(":many", "column"), (":exec", "parameter")] {
+            let mut request = request(serde_json::json!({}));
+            request.queries.push(multirange_query(cmd));
+            let err = RustGenerator::new(request).unwrap().generate().unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!(
+                    "Query Free: {kind} free has type datemultirange, which sqlx can't decode or \
+                     encode; convert it from or to an array of ranges in SQL, or map it with an \
+                     override"
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn accepts_overridden_multiranges() {
+        let mut request = request(serde_json::json!({
+            "overrides": [{ "db_type": "datemultirange", "rust_type": "DateRanges" }]
+        }));
+        request.queries.push(multirange_query(":many"));
+        assert!(RustGenerator::new(request).unwrap().generate().is_ok());
+    }
+
+    /// A `:copyfrom` query with a parameter of a catalog type, in a schema
+    /// with a `mood` enum, a `tagged` composite with a `mood` attribute and
+    /// a `point2` composite of plain attributes.
+    fn copy_request(sql_type: &str, is_array: bool) -> GenerateRequest {
+        let mut request = request(serde_json::json!({
+            "composite_types": [
+                { "name": "tagged", "attributes": [{ "name": "mood", "db_type": "mood" }] },
+                {
+                    "name": "point2",
+                    "attributes": [
+                        { "name": "x", "db_type": "float8" },
+                        { "name": "tags", "db_type": "text", "is_array": true }
+                    ]
+                }
+            ]
+        }));
+        request.catalog = serde_json::from_value(serde_json::json!({
+            "comment": "",
+            "default_schema": "public",
+            "name": "",
+            "schemas": [{
+                "comment": "",
+                "name": "public",
+                "tables": [],
+                "enums": [{ "name": "mood", "vals": ["happy", "sad"], "comment": "" }],
+                "composite_types": [
+                    { "name": "tagged", "comment": "" },
+                    { "name": "point2", "comment": "" }
+                ]
+            }]
+        }))
+        .unwrap();
+        let mut param = column("value", sql_type, false);
+        param["type"]["schema"] = "public".into();
+        param["is_array"] = is_array.into();
+        request.queries.push(
+            serde_json::from_value(serde_json::json!({
+                "text": "INSERT INTO things (value) VALUES ($1)",
+                "name": "CopyThings",
+                "cmd": ":copyfrom",
+                "columns": [],
+                "params": [{ "number": 1, "column": param }],
+                "comments": [],
+                "filename": "queries.sql",
+                "insert_into_table": { "catalog": "", "schema": "", "name": "things" }
+            }))
+            .unwrap(),
+        );
+        request
+    }
+
+    #[test]
+    fn rejects_copyfrom_types_with_catalog_oids() {
+        for (sql_type, is_array) in [("mood", true), ("tagged", false), ("point2", true)] {
+            let request = copy_request(sql_type, is_array);
+            let err = RustGenerator::new(request).unwrap().generate().unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!(
+                    "Query CopyThings: parameter value has type {sql_type}, which :copyfrom \
+                     can't encode since sqlx leaves the OIDs of enum and composite types in its \
+                     values unset; use :exec or a batch instead"
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn accepts_copyfrom_types_with_known_oids() {
+        for (sql_type, is_array) in [("mood", false), ("point2", false)] {
+            let request = copy_request(sql_type, is_array);
+            assert!(RustGenerator::new(request).unwrap().generate().is_ok());
         }
     }
+
+    fn rewrite(sql: &str, number: i32) -> Option<String> {
+        RustGenerator::rewrite_slice(sql, &slice_param(number, "ids"))
+    }
+
+    #[test]
+    fn rewrites_in_list() {
+        assert_eq!(
+            rewrite("SELECT * FROM users WHERE id IN ($1)", 1).as_deref(),
+            Some("SELECT * FROM users WHERE id = ANY($1)")
+        );
+        assert_eq!(
+            rewrite("SELECT * FROM users WHERE id in ( $1 )", 1).as_deref(),
+            Some("SELECT * FROM users WHERE id = ANY($1)")
+        );
+    }
+
+    #[test]
+    fn rewrites_not_in_list() {
+        assert_eq!(
+            rewrite("DELETE FROM users WHERE id NOT IN ($2)", 2).as_deref(),
+            Some("DELETE FROM users WHERE id <> ALL($2)")
+        );
+        assert_eq!(
+            rewrite("DELETE FROM users WHERE id not\n  in ($2)", 2).as_deref(),
+            Some("DELETE FROM users WHERE id <> ALL($2)")
+        );
+    }
+
+    #[test]
+    fn rewrites_slice_comment_placeholders() {
+        assert_eq!(
+            rewrite("SELECT 1 WHERE id IN (/*SLICE:ids*/?)", 1).as_deref(),
+            Some("SELECT 1 WHERE id = ANY($1)")
+        );
+        assert_eq!(
+            rewrite("SELECT 1 WHERE id IN (/*SLICE:ids*/$1)", 1).as_deref(),
+            Some("SELECT 1 WHERE id = ANY($1)")
+        );
+    }
+
+    #[test]
+    fn does_not_match_longer_placeholder() {
+        assert_eq!(
+            rewrite("SELECT 1 WHERE a = $10 AND id IN ($1)", 1).as_deref(),
+            Some("SELECT 1 WHERE a = $10 AND id = ANY($1)")
+        );
+        assert_eq!(rewrite("SELECT 1 WHERE id IN ($10)", 1), None);
+    }
+
+    #[test]
+    fn requires_a_single_element_in_list() {
+        assert_eq!(rewrite("SELECT 1 WHERE id IN ($1, 2)", 1), None);
+        assert_eq!(rewrite("SELECT 1 WHERE id = $1", 1), None);
+        assert_eq!(rewrite("SELECT 1 WHERE id = ANY($1)", 1), None);
+    }
+
+    #[test]
+    fn in_list_needs_in_as_a_whole_word() {
+        let sql = "SELECT join($1)";
+        let start = sql.find('$').unwrap();
+        assert_eq!(RustGenerator::in_list_around(sql, start, start + 2), None);
+
+        let sql = "SELECT 1 WHERE anot IN ($1)";
+        let start = sql.find('$').unwrap();
+        assert_eq!(
+            RustGenerator::in_list_around(sql, start, start + 2),
+            Some((20, sql.len(), false))
+        );
+    }
+
+    fn batch_query(cmd: &str, text: &str, params: &[(i32, &str)]) -> Query {
+        let params: Vec<_> = params
+            .iter()
+            .map(|(number, sql_type)| {
+                serde_json::json!({
+                    "number": number,
+                    "column": column(&format!("p{number}"), sql_type, false)
+                })
+            })
+            .collect();
+        serde_json::from_value(serde_json::json!({
+            "text": text,
+            "name": "Batch",
+            "cmd": cmd,
+            "columns": [],
+            "params": params,
+            "comments": [],
+            "filename": "queries.sql"
+        }))
+        .unwrap()
+    }
+
+    fn batch_sql(cmd: &str, text: &str, params: &[(i32, &str)]) -> Result<String> {
+        RustGenerator::batch_sql(&batch_query(cmd, text, params))
+    }
+
+    #[test]
+    fn batches_select_per_item() {
+        assert_eq!(
+            batch_sql(
+                ":batchmany",
+                "SELECT * FROM t WHERE a = $1 -- note",
+                &[(1, "int4")]
+            )
+            .unwrap(),
+            "SELECT sqlc_q.*, sqlc_batch.sqlc_ord\n\
+             FROM unnest($1::int4[]) WITH ORDINALITY AS sqlc_batch (sqlc_p1, sqlc_ord)\n\
+             CROSS JOIN LATERAL (\n\
+             SELECT sqlc_inner.*, row_number() OVER () AS sqlc_row\n\
+             FROM (\n\
+             SELECT * FROM t WHERE a = sqlc_batch.sqlc_p1 -- note\n\
+             ) AS sqlc_inner\n\
+             ) AS sqlc_q\n\
+             ORDER BY sqlc_batch.sqlc_ord, sqlc_q.sqlc_row"
+        );
+    }
+
+    #[test]
+    fn batches_update_after_existing_from_items() {
+        assert_eq!(
+            batch_sql(
+                ":batchone",
+                "UPDATE t SET a = $2 FROM u WHERE t.id = u.id AND u.x = $1 RETURNING *",
+                &[(2, "text"), (1, "int4")]
+            )
+            .unwrap(),
+            "UPDATE t SET a = sqlc_batch.sqlc_p2 FROM u,\n\
+             unnest($1::int4[], $2::text[]) WITH ORDINALITY AS sqlc_batch (sqlc_p1, sqlc_p2, sqlc_ord)\n\
+             WHERE t.id = u.id AND u.x = sqlc_batch.sqlc_p1 RETURNING *, sqlc_batch.sqlc_ord"
+        );
+    }
+
+    #[test]
+    fn batches_delete_with_using() {
+        assert_eq!(
+            batch_sql(
+                ":batchexec",
+                "DELETE FROM t WHERE id = $1",
+                &[(1, "bigserial")]
+            )
+            .unwrap(),
+            "DELETE FROM t\n\
+             USING unnest($1::int8[]) WITH ORDINALITY AS sqlc_batch (sqlc_p1, sqlc_ord)\n\
+             WHERE id = sqlc_batch.sqlc_p1"
+        );
+    }
+
+    #[test]
+    fn batches_insert_values_as_select() {
+        assert_eq!(
+            batch_sql(
+                ":batchone",
+                "INSERT INTO t (a, b) VALUES ($1, lower($2)) ON CONFLICT (a) DO UPDATE SET b = EXCLUDED.b RETURNING id;",
+                &[(1, "int4"), (2, "text")]
+            )
+            .unwrap(),
+            "INSERT INTO t (a, b) SELECT sqlc_batch.sqlc_p1, lower(sqlc_batch.sqlc_p2)\n\
+             FROM unnest($1::int4[], $2::text[]) WITH ORDINALITY AS sqlc_batch (sqlc_p1, sqlc_p2, sqlc_ord)\n\
+             ORDER BY sqlc_batch.sqlc_ord\n\
+             ON CONFLICT (a) DO UPDATE SET b = EXCLUDED.b RETURNING id"
+        );
+    }
+
+    #[test]
+    fn rejects_batches_that_cannot_be_matched_to_items() {
+        let cases = [
+            (
+                ":batchone",
+                "INSERT INTO t (a) VALUES ($1) ON CONFLICT DO NOTHING RETURNING id",
+                "Query Batch: :batchone INSERT queries must insert one VALUES row per item and \
+                 can't skip rows with ON CONFLICT, because rows are matched to items by position",
+            ),
+            (
+                ":batchmany",
+                "INSERT INTO t (a) SELECT x FROM u WHERE y = $1 RETURNING id",
+                "Query Batch: :batchmany INSERT queries must insert one VALUES row per item and \
+                 can't skip rows with ON CONFLICT, because rows are matched to items by position",
+            ),
+            (
+                ":batchexec",
+                "INSERT INTO t (a, b) VALUES ($1, DEFAULT)",
+                "Query Batch: :batchexec INSERT queries can't use DEFAULT, because the inserted \
+                 rows become a SELECT",
+            ),
+            (
+                ":batchexec",
+                "WITH x AS (SELECT $1 AS a) DELETE FROM t USING x WHERE t.a = x.a",
+                "Query Batch: :batchexec queries can't use parameters in the WITH clause of an \
+                 INSERT, UPDATE or DELETE",
+            ),
+            (
+                ":batchexec",
+                "MERGE INTO t USING u ON t.id = u.id AND u.id = $1 WHEN MATCHED THEN DELETE",
+                "Query Batch: :batchexec queries must be a SELECT, INSERT, UPDATE or DELETE",
+            ),
+        ];
+        for (cmd, text, message) in cases {
+            let err = batch_sql(cmd, text, &[(1, "int4")]).unwrap_err();
+            assert_eq!(err.to_string(), message, "{text}");
+        }
+    }
+
+    #[test]
+    fn rejects_array_batch_params() {
+        let mut query = batch_query(":batchexec", "DELETE FROM t WHERE id = ANY($1)", &[]);
+        query.params.push(slice_param(1, "ids"));
+        assert_eq!(
+            RustGenerator::batch_sql(&query).unwrap_err().to_string(),
+            "Query Batch: :batchexec parameter ids can't be an array, because each parameter is \
+             bound as an array of the items' values"
+        );
+    }
 }
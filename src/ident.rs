@@ -0,0 +1,100 @@
+//! Turning SQL names into valid Rust identifiers.
+
+use heck::{ToPascalCase, ToShoutySnakeCase, ToSnakeCase};
+
+/// Keywords, including reserved ones, that are valid as raw identifiers.
+const KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "do", "dyn",
+    "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl", "in", "let",
+    "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref", "return",
+    "static", "struct", "trait", "true", "try", "type", "typeof", "unsafe", "unsized", "use",
+    "virtual", "where", "while", "yield",
+];
+
+/// Keywords that can't be raw identifiers.
+const PATH_KEYWORDS: &[&str] = &["crate", "self", "Self", "super", "_"];
+
+pub fn snake_case(name: &str) -> String {
+    sanitize(name.to_snake_case())
+}
+
+pub fn pascal_case(name: &str) -> String {
+    sanitize(name.to_pascal_case())
+}
+
+pub fn shouty_snake_case(name: &str) -> String {
+    sanitize(name.to_shouty_snake_case())
+}
+
+/// Makes a case-converted name a valid identifier. Keywords become raw
+/// identifiers like `r#type`, or get a `_` suffix where Rust doesn't allow
+/// that, and a leading digit gets a `_` prefix. An empty name stays empty so
+/// callers can pick a fallback.
+pub fn sanitize(ident: String) -> String {
+    if ident.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{ident}")
+    } else if PATH_KEYWORDS.contains(&ident.as_str()) {
+        format!("{ident}_")
+    } else if KEYWORDS.contains(&ident.as_str()) {
+        format!("r#{ident}")
+    } else {
+        ident
+    }
+}
+
+/// An identifier without its `r#` prefix, which is the name serde and sqlx
+/// derives see and the base for names built from it.
+pub fn unraw(ident: &str) -> &str {
+    ident.strip_prefix("r#").unwrap_or(ident)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keywords_become_raw_identifiers() {
+        assert_eq!(snake_case("type"), "r#type");
+        assert_eq!(snake_case("Match"), "r#match");
+        assert_eq!(pascal_case("async"), "Async");
+        assert_eq!(sanitize("gen".to_string()), "r#gen");
+    }
+
+    #[test]
+    fn path_keywords_get_a_suffix() {
+        assert_eq!(snake_case("self"), "self_");
+        assert_eq!(pascal_case("self"), "Self_");
+        assert_eq!(snake_case("super"), "super_");
+        assert_eq!(snake_case("crate"), "crate_");
+        assert_eq!(sanitize("_".to_string()), "__");
+    }
+
+    #[test]
+    fn leading_digits_get_a_prefix() {
+        assert_eq!(snake_case("1st_place"), "_1st_place");
+        assert_eq!(pascal_case("2fa"), "_2fa");
+        assert_eq!(shouty_snake_case("3d view"), "_3D_VIEW");
+    }
+
+    #[test]
+    fn plain_names_are_unchanged() {
+        assert_eq!(snake_case("created_at"), "created_at");
+        assert_eq!(sanitize(String::new()), "");
+    }
+
+    #[test]
+    fn sanitized_names_can_clash() {
+        // Distinct SQL names the generator has to suffix or reject as duplicates
+        assert_eq!(snake_case("self"), snake_case("self_"));
+        assert_eq!(snake_case("1st"), snake_case("_1st"));
+        assert_eq!(unraw(&snake_case("Type")), unraw("type"));
+    }
+
+    #[test]
+    fn unraw_strips_only_the_raw_prefix() {
+        assert_eq!(unraw("r#type"), "type");
+        assert_eq!(unraw("type"), "type");
+        assert_eq!(unraw("self_"), "self_");
+        assert_eq!(unraw("r#r#x"), "r#x");
+    }
+}
@@ -1,13 +1,18 @@
+pub mod codec;
 pub mod generator;
+pub mod ident;
+pub mod proto;
+pub mod sql;
 pub mod type_mapping;
 pub mod types;
 
+pub use codec::Format;
 pub use generator::RustGenerator;
 pub use types::{File, GenerateRequest, GenerateResponse, PluginOptions};
 
 use anyhow::Result;
 
 pub fn generate_code(request: GenerateRequest) -> Result<GenerateResponse> {
-    let generator = RustGenerator::new(request);
+    let generator = RustGenerator::new(request)?;
     generator.generate()
 }
@@ -1,21 +1,45 @@
-use anyhow::{Context, Result};
-use sqlc_gen_rust::{generate_code, GenerateRequest};
-use std::io::{self, Read};
+use anyhow::{bail, Context, Result};
+use sqlc_gen_rust::codec::{self, Format};
+use std::io::{self, Read, Write};
+
+const GENERATE_METHOD: &str = "/plugin.CodegenService/Generate";
+
+fn parse_format() -> Result<Option<Format>> {
+    let mut args = std::env::args().skip(1);
+    let mut format = None;
+
+    while let Some(arg) = args.next() {
+        let value = match arg.strip_prefix("--format=") {
+            Some(value) => value.to_string(),
+            None if arg == "--format" => args.next().context("Missing value for --format")?,
+            // sqlc passes the RPC method it wants to invoke as an argument
+            None if arg == GENERATE_METHOD => continue,
+            None => bail!("Unknown argument '{arg}'"),
+        };
+
+        format = match value.as_str() {
+            "auto" => None,
+            value => Some(value.parse()?),
+        };
+    }
+
+    Ok(format)
+}
 
 fn main() -> Result<()> {
-    let mut input = String::new();
+    let format = parse_format()?;
+
+    let mut input = Vec::new();
     io::stdin()
-        .read_to_string(&mut input)
+        .read_to_end(&mut input)
         .context("Failed to read from stdin")?;
 
-    let request: GenerateRequest =
-        serde_json::from_str(&input).context("Failed to parse GenerateRequest from JSON")?;
-
-    let response = generate_code(request)?;
-
-    let output =
-        serde_json::to_string(&response).context("Failed to serialize GenerateResponse to JSON")?;
+    let output = codec::run(&input, format)?;
 
-    println!("{output}");
+    let mut stdout = io::stdout().lock();
+    stdout
+        .write_all(&output)
+        .context("Failed to write GenerateResponse to stdout")?;
+    stdout.flush().context("Failed to flush stdout")?;
     Ok(())
 }
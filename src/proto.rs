@@ -0,0 +1,416 @@
+//! Protobuf messages for sqlc's `plugin/codegen.proto`.
+//!
+//! sqlc talks to process plugins with protobuf-encoded `GenerateRequest` and
+//! `GenerateResponse` messages unless `format: json` is configured. The
+//! messages below mirror the upstream proto definition field for field and
+//! are converted into the serde types in [`crate::types`] so the generator
+//! never has to care which wire format was used.
+
+use crate::types;
+use anyhow::{Context, Result};
+use prost::Message;
+
+#[derive(Clone, PartialEq, Message)]
+pub struct File {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(bytes = "vec", tag = "2")]
+    pub contents: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Settings {
+    #[prost(string, tag = "1")]
+    pub version: String,
+    #[prost(string, tag = "2")]
+    pub engine: String,
+    #[prost(string, repeated, tag = "3")]
+    pub schema: Vec<String>,
+    #[prost(string, repeated, tag = "4")]
+    pub queries: Vec<String>,
+    #[prost(message, optional, tag = "12")]
+    pub codegen: Option<Codegen>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Codegen {
+    #[prost(string, tag = "1")]
+    pub out: String,
+    #[prost(string, tag = "2")]
+    pub plugin: String,
+    #[prost(bytes = "vec", tag = "3")]
+    pub options: Vec<u8>,
+    #[prost(string, repeated, tag = "4")]
+    pub env: Vec<String>,
+    #[prost(message, optional, tag = "5")]
+    pub process: Option<Process>,
+    #[prost(message, optional, tag = "6")]
+    pub wasm: Option<Wasm>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Process {
+    #[prost(string, tag = "1")]
+    pub cmd: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Wasm {
+    #[prost(string, tag = "1")]
+    pub url: String,
+    #[prost(string, tag = "2")]
+    pub sha256: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Catalog {
+    #[prost(string, tag = "1")]
+    pub comment: String,
+    #[prost(string, tag = "2")]
+    pub default_schema: String,
+    #[prost(string, tag = "3")]
+    pub name: String,
+    #[prost(message, repeated, tag = "4")]
+    pub schemas: Vec<Schema>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Schema {
+    #[prost(string, tag = "1")]
+    pub comment: String,
+    #[prost(string, tag = "2")]
+    pub name: String,
+    #[prost(message, repeated, tag = "3")]
+    pub tables: Vec<Table>,
+    #[prost(message, repeated, tag = "4")]
+    pub enums: Vec<Enum>,
+    #[prost(message, repeated, tag = "5")]
+    pub composite_types: Vec<CompositeType>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct CompositeType {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub comment: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Enum {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, repeated, tag = "2")]
+    pub vals: Vec<String>,
+    #[prost(string, tag = "3")]
+    pub comment: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Table {
+    #[prost(message, optional, tag = "1")]
+    pub rel: Option<Identifier>,
+    #[prost(message, repeated, tag = "2")]
+    pub columns: Vec<Column>,
+    #[prost(string, tag = "3")]
+    pub comment: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Identifier {
+    #[prost(string, tag = "1")]
+    pub catalog: String,
+    #[prost(string, tag = "2")]
+    pub schema: String,
+    #[prost(string, tag = "3")]
+    pub name: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Column {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(bool, tag = "3")]
+    pub not_null: bool,
+    #[prost(bool, tag = "4")]
+    pub is_array: bool,
+    #[prost(string, tag = "5")]
+    pub comment: String,
+    #[prost(int32, tag = "6")]
+    pub length: i32,
+    #[prost(bool, tag = "7")]
+    pub is_named_param: bool,
+    #[prost(bool, tag = "8")]
+    pub is_func_call: bool,
+    #[prost(string, tag = "9")]
+    pub scope: String,
+    #[prost(message, optional, tag = "10")]
+    pub table: Option<Identifier>,
+    #[prost(string, tag = "11")]
+    pub table_alias: String,
+    #[prost(message, optional, tag = "12")]
+    pub r#type: Option<Identifier>,
+    #[prost(bool, tag = "13")]
+    pub is_sqlc_slice: bool,
+    #[prost(message, optional, tag = "14")]
+    pub embed_table: Option<Identifier>,
+    #[prost(string, tag = "15")]
+    pub original_name: String,
+    #[prost(bool, tag = "16")]
+    pub unsigned: bool,
+    #[prost(int32, tag = "17")]
+    pub array_dims: i32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Query {
+    #[prost(string, tag = "1")]
+    pub text: String,
+    #[prost(string, tag = "2")]
+    pub name: String,
+    #[prost(string, tag = "3")]
+    pub cmd: String,
+    #[prost(message, repeated, tag = "4")]
+    pub columns: Vec<Column>,
+    #[prost(message, repeated, tag = "5")]
+    pub params: Vec<Parameter>,
+    #[prost(string, repeated, tag = "6")]
+    pub comments: Vec<String>,
+    #[prost(string, tag = "7")]
+    pub filename: String,
+    #[prost(message, optional, tag = "8")]
+    pub insert_into_table: Option<Identifier>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Parameter {
+    #[prost(int32, tag = "1")]
+    pub number: i32,
+    #[prost(message, optional, tag = "2")]
+    pub column: Option<Column>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct GenerateRequest {
+    #[prost(message, optional, tag = "1")]
+    pub settings: Option<Settings>,
+    #[prost(message, optional, tag = "2")]
+    pub catalog: Option<Catalog>,
+    #[prost(message, repeated, tag = "3")]
+    pub queries: Vec<Query>,
+    #[prost(string, tag = "4")]
+    pub sqlc_version: String,
+    #[prost(bytes = "vec", tag = "5")]
+    pub plugin_options: Vec<u8>,
+    #[prost(bytes = "vec", tag = "6")]
+    pub global_options: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct GenerateResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub files: Vec<File>,
+}
+
+pub fn decode_request(input: &[u8]) -> Result<types::GenerateRequest> {
+    let request =
+        GenerateRequest::decode(input).context("Failed to decode GenerateRequest from protobuf")?;
+    request.try_into()
+}
+
+pub fn encode_response(response: types::GenerateResponse) -> Vec<u8> {
+    GenerateResponse::from(response).encode_to_vec()
+}
+
+fn non_empty(value: String) -> Option<String> {
+    if value.is_empty() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+fn json_options(bytes: &[u8]) -> Result<Option<serde_json::Value>> {
+    if bytes.is_empty() {
+        return Ok(None);
+    }
+    serde_json::from_slice(bytes)
+        .map(Some)
+        .context("Failed to parse plugin options as JSON")
+}
+
+impl TryFrom<GenerateRequest> for types::GenerateRequest {
+    type Error = anyhow::Error;
+
+    fn try_from(request: GenerateRequest) -> Result<Self> {
+        Ok(Self {
+            settings: request.settings.unwrap_or_default().try_into()?,
+            catalog: request.catalog.unwrap_or_default().into(),
+            queries: request.queries.into_iter().map(Into::into).collect(),
+            sqlc_version: request.sqlc_version,
+            plugin_options: json_options(&request.plugin_options)?
+                .unwrap_or(serde_json::Value::Null),
+            global_options: json_options(&request.global_options)?,
+        })
+    }
+}
+
+impl TryFrom<Settings> for types::Settings {
+    type Error = anyhow::Error;
+
+    fn try_from(settings: Settings) -> Result<Self> {
+        Ok(Self {
+            version: settings.version,
+            engine: settings.engine,
+            schema: settings.schema,
+            queries: settings.queries,
+            codegen: settings.codegen.map(TryInto::try_into).transpose()?,
+        })
+    }
+}
+
+impl TryFrom<Codegen> for types::Codegen {
+    type Error = anyhow::Error;
+
+    fn try_from(codegen: Codegen) -> Result<Self> {
+        Ok(Self {
+            out: codegen.out,
+            plugin: codegen.plugin,
+            options: json_options(&codegen.options)?,
+            env: codegen.env,
+            process: codegen
+                .process
+                .map(|process| types::Process { cmd: process.cmd }),
+            wasm: codegen.wasm.map(|wasm| types::Wasm {
+                url: wasm.url,
+                sha256: wasm.sha256,
+            }),
+        })
+    }
+}
+
+impl From<Catalog> for types::Catalog {
+    fn from(catalog: Catalog) -> Self {
+        Self {
+            comment: non_empty(catalog.comment),
+            default_schema: catalog.default_schema,
+            name: catalog.name,
+            schemas: catalog.schemas.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<Schema> for types::Schema {
+    fn from(schema: Schema) -> Self {
+        Self {
+            comment: non_empty(schema.comment),
+            name: schema.name,
+            tables: schema.tables.into_iter().map(Into::into).collect(),
+            enums: schema.enums.into_iter().map(Into::into).collect(),
+            composite_types: schema.composite_types.into_iter().map(Into::into).collect(),
+            domains: Vec::new(),
+        }
+    }
+}
+
+impl From<CompositeType> for types::CompositeType {
+    fn from(composite: CompositeType) -> Self {
+        Self {
+            name: composite.name,
+            comment: non_empty(composite.comment),
+            columns: Vec::new(),
+        }
+    }
+}
+
+impl From<Enum> for types::Enum {
+    fn from(enum_def: Enum) -> Self {
+        Self {
+            name: enum_def.name,
+            vals: enum_def.vals,
+            comment: non_empty(enum_def.comment),
+        }
+    }
+}
+
+impl From<Table> for types::Table {
+    fn from(table: Table) -> Self {
+        Self {
+            rel: table.rel.unwrap_or_default().into(),
+            columns: table.columns.into_iter().map(Into::into).collect(),
+            comment: non_empty(table.comment),
+        }
+    }
+}
+
+impl From<Identifier> for types::Identifier {
+    fn from(identifier: Identifier) -> Self {
+        Self {
+            catalog: identifier.catalog,
+            schema: identifier.schema,
+            name: identifier.name,
+        }
+    }
+}
+
+impl From<Column> for types::Column {
+    fn from(column: Column) -> Self {
+        Self {
+            name: column.name,
+            not_null: column.not_null,
+            is_array: column.is_array,
+            comment: non_empty(column.comment),
+            length: (column.length > 0).then_some(column.length),
+            is_named_param: column.is_named_param,
+            is_func_call: column.is_func_call,
+            scope: non_empty(column.scope),
+            table: column.table.map(Into::into),
+            table_alias: non_empty(column.table_alias),
+            r#type: column.r#type.map(Into::into),
+            is_sqlc_slice: column.is_sqlc_slice,
+            embed_table: column.embed_table.map(Into::into),
+            original_name: non_empty(column.original_name),
+            unsigned: column.unsigned,
+            array_dims: column.array_dims,
+        }
+    }
+}
+
+impl From<Query> for types::Query {
+    fn from(query: Query) -> Self {
+        Self {
+            text: query.text,
+            name: query.name,
+            cmd: query.cmd,
+            columns: query.columns.into_iter().map(Into::into).collect(),
+            params: query.params.into_iter().map(Into::into).collect(),
+            comments: query.comments,
+            filename: query.filename,
+            insert_into_table: query.insert_into_table.map(Into::into),
+        }
+    }
+}
+
+impl From<Parameter> for types::Parameter {
+    fn from(param: Parameter) -> Self {
+        Self {
+            number: param.number,
+            column: param.column.unwrap_or_default().into(),
+        }
+    }
+}
+
+impl From<types::GenerateResponse> for GenerateResponse {
+    fn from(response: types::GenerateResponse) -> Self {
+        Self {
+            files: response
+                .files
+                .into_iter()
+                .map(|file| File {
+                    name: file.name,
+                    contents: file.contents,
+                })
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,312 @@
+//! Helpers for embedding query text in generated Rust source.
+
+use std::ops::Range;
+
+/// A Rust string literal holding `sql` verbatim.
+///
+/// This is a raw string with the fewest `#`s that keep any `"#...` in the
+/// text from closing it early. Text with a carriage return falls back to
+/// an escaped string literal, since rustc turns a CRLF inside a raw string
+/// into a bare newline.
+pub fn string_literal(sql: &str) -> String {
+    if sql.contains('\r') {
+        return format!("{sql:?}");
+    }
+
+    let hashes = sql
+        .match_indices('"')
+        .map(|(i, _)| sql[i + 1..].chars().take_while(|&c| c == '#').count() + 1)
+        .max()
+        .unwrap_or(0);
+    let hashes = "#".repeat(hashes);
+    format!("r{hashes}\"{sql}\"{hashes}")
+}
+
+/// Collapses every run of whitespace into a single space and trims the
+/// ends, leaving string literals, quoted identifiers and comments untouched.
+/// A line comment keeps the newline that ends it.
+pub fn normalize_whitespace(sql: &str) -> String {
+    let mut output = String::with_capacity(sql.len());
+    let mut rest = sql.trim();
+    let mut pending_space = false;
+
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            pending_space = true;
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+        if pending_space {
+            output.push(' ');
+            pending_space = false;
+        }
+
+        let token = &rest[..token_len(rest)];
+        output.push_str(token);
+        rest = &rest[token.len()..];
+        if token.ends_with('\n') {
+            rest = rest.trim_start();
+        }
+    }
+
+    output
+}
+
+/// A token of query text other than whitespace, with its byte offset and
+/// how deeply it is nested in parentheses. String literals, quoted
+/// identifiers and comments are single tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token<'a> {
+    pub offset: usize,
+    pub text: &'a str,
+    pub depth: usize,
+}
+
+impl Token<'_> {
+    /// Whether this is the unquoted keyword or identifier `word`, in any case.
+    pub fn is_word(&self, word: &str) -> bool {
+        self.text.eq_ignore_ascii_case(word)
+    }
+
+    /// The number of a `$n` placeholder.
+    pub fn placeholder(&self) -> Option<i32> {
+        self.text.strip_prefix('$')?.parse().ok()
+    }
+
+    pub fn is_comment(&self) -> bool {
+        self.text.starts_with("--") || self.text.starts_with("/*")
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.offset..self.offset + self.text.len()
+    }
+}
+
+/// Splits `sql` into tokens. A parenthesis has the depth outside of it.
+pub fn tokens(sql: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut offset = 0;
+    let mut depth: usize = 0;
+
+    while let Some(c) = sql[offset..].chars().next() {
+        if c.is_whitespace() {
+            offset += c.len_utf8();
+            continue;
+        }
+
+        let text = &sql[offset..offset + token_len(&sql[offset..])];
+        if text == ")" {
+            depth = depth.saturating_sub(1);
+        }
+        tokens.push(Token {
+            offset,
+            text,
+            depth,
+        });
+        if text == "(" {
+            depth += 1;
+        }
+        offset += text.len();
+    }
+
+    tokens
+}
+
+/// Length of the token at the start of `sql` that must be copied verbatim.
+fn token_len(sql: &str) -> usize {
+    let bytes = sql.as_bytes();
+    match bytes {
+        [b'-', b'-', ..] => sql.find('\n').map_or(sql.len(), |i| i + 1),
+        [b'/', b'*', ..] => block_comment_len(sql),
+        [b'\'', ..] => quoted_len(sql, b'\'', false),
+        [b'"', ..] => quoted_len(sql, b'"', false),
+        [b'e' | b'E', b'\'', ..] => 1 + quoted_len(&sql[1..], b'\'', true),
+        [b'$', c, ..] if c.is_ascii_digit() => {
+            1 + bytes[1..].iter().take_while(|c| c.is_ascii_digit()).count()
+        }
+        [b'$', ..] => dollar_quoted_len(sql).unwrap_or(1),
+        [c, ..] if is_word_byte(*c) => bytes.iter().take_while(|c| is_word_byte(**c)).count(),
+        _ => sql.chars().next().map_or(0, char::len_utf8),
+    }
+}
+
+fn is_word_byte(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_' || !c.is_ascii()
+}
+
+/// `/* ... */`, which nests in PostgreSQL.
+fn block_comment_len(sql: &str) -> usize {
+    let bytes = sql.as_bytes();
+    let mut depth = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match &bytes[i..] {
+            [b'/', b'*', ..] => {
+                depth += 1;
+                i += 2;
+            }
+            [b'*', b'/', ..] => {
+                depth -= 1;
+                i += 2;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    sql.len()
+}
+
+/// A literal delimited by `quote`, where a doubled quote is an escaped one
+/// and, in `E'...'` strings, so is a backslash-escaped one.
+fn quoted_len(sql: &str, quote: u8, backslash_escapes: bool) -> usize {
+    let bytes = sql.as_bytes();
+    let mut i = 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' if backslash_escapes => i += 2,
+            c if c == quote && bytes.get(i + 1) == Some(&quote) => i += 2,
+            c if c == quote => return i + 1,
+            _ => i += 1,
+        }
+    }
+    sql.len()
+}
+
+/// A `$tag$ ... $tag$` string.
+fn dollar_quoted_len(sql: &str) -> Option<usize> {
+    let tag_len = sql[1..].find('$')? + 2;
+    let tag = &sql[..tag_len];
+    let name = &tag[1..tag_len - 1];
+    if name.starts_with(|c: char| c.is_ascii_digit()) || !name.bytes().all(is_word_byte) {
+        return None;
+    }
+    let end = sql[tag_len..].find(tag)?;
+    Some(tag_len + end + tag_len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn string_literal_without_quotes_needs_no_hashes() {
+        assert_eq!(string_literal("SELECT 1"), "r\"SELECT 1\"");
+    }
+
+    #[test]
+    fn string_literal_with_quotes() {
+        assert_eq!(
+            string_literal(r#"SELECT "id" FROM t"#),
+            r##"r#"SELECT "id" FROM t"#"##
+        );
+        assert_eq!(
+            string_literal(r##"SELECT '"#' AS x"##),
+            r###"r##"SELECT '"#' AS x"##"###
+        );
+    }
+
+    #[test]
+    fn string_literal_keeps_newlines_raw() {
+        assert_eq!(
+            string_literal("SELECT 1\nFROM t\n"),
+            "r\"SELECT 1\nFROM t\n\""
+        );
+    }
+
+    #[test]
+    fn string_literal_escapes_carriage_returns() {
+        assert_eq!(
+            string_literal("SELECT 1\r\nFROM t"),
+            r#""SELECT 1\r\nFROM t""#
+        );
+        assert_eq!(string_literal("SELECT 1\rFROM t"), r#""SELECT 1\rFROM t""#);
+    }
+
+    #[test]
+    fn collapses_whitespace() {
+        assert_eq!(
+            normalize_whitespace("  SELECT id,\n\t  name\r\n FROM users  "),
+            "SELECT id, name FROM users"
+        );
+    }
+
+    #[test]
+    fn keeps_quoted_text() {
+        assert_eq!(
+            normalize_whitespace("SELECT 'a  b',  \"odd  name\" FROM t"),
+            "SELECT 'a  b', \"odd  name\" FROM t"
+        );
+        assert_eq!(
+            normalize_whitespace("SELECT 'it''s  here'  FROM t"),
+            "SELECT 'it''s  here' FROM t"
+        );
+    }
+
+    #[test]
+    fn keeps_escape_strings() {
+        assert_eq!(
+            normalize_whitespace(r"SELECT E'a\'  b'   , 1"),
+            r"SELECT E'a\'  b' , 1"
+        );
+    }
+
+    #[test]
+    fn keeps_dollar_quoted_strings() {
+        assert_eq!(
+            normalize_whitespace("SELECT $fn$ a   b $fn$,   $$ c  d $$"),
+            "SELECT $fn$ a   b $fn$, $$ c  d $$"
+        );
+    }
+
+    #[test]
+    fn placeholders_are_not_dollar_quotes() {
+        assert_eq!(
+            normalize_whitespace("WHERE a = $1   AND b = $2 AND c = '$1  x$1'"),
+            "WHERE a = $1 AND b = $2 AND c = '$1  x$1'"
+        );
+    }
+
+    #[test]
+    fn tokens_track_parentheses() {
+        let tokens = tokens("SELECT f($1) -- a ( b\nFROM t WHERE x = '('");
+        let tokens: Vec<_> = tokens.iter().map(|t| (t.text, t.depth)).collect();
+        assert_eq!(
+            tokens,
+            [
+                ("SELECT", 0),
+                ("f", 0),
+                ("(", 0),
+                ("$1", 1),
+                (")", 0),
+                ("-- a ( b\n", 0),
+                ("FROM", 0),
+                ("t", 0),
+                ("WHERE", 0),
+                ("x", 0),
+                ("=", 0),
+                ("'('", 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn placeholders_are_tokens() {
+        let tokens = tokens("a = $12 AND b = $x$ $1 $x$");
+        let placeholders: Vec<_> = tokens.iter().filter_map(Token::placeholder).collect();
+        assert_eq!(placeholders, [12]);
+    }
+
+    #[test]
+    fn keeps_comments() {
+        assert_eq!(
+            normalize_whitespace("SELECT 1 -- one  two\n   FROM t"),
+            "SELECT 1 -- one  two\nFROM t"
+        );
+        assert_eq!(
+            normalize_whitespace("SELECT /* a  /* nested */  b */   1"),
+            "SELECT /* a  /* nested */  b */ 1"
+        );
+    }
+}
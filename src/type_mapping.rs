@@ -1,18 +1,217 @@
-use crate::types::Identifier;
+use crate::ident;
+use crate::types::{Catalog, Column, Domain, Identifier, TypeOverride};
+use anyhow::{bail, Result};
+use std::collections::{HashMap, HashSet};
 
-pub struct TypeMapper;
+pub struct TypeMapper {
+    default_schema: String,
+    overrides: Vec<TypeOverride>,
+    /// Rust types generated for catalog types, keyed by schema and SQL name.
+    catalog_types: HashMap<(String, String), String>,
+    /// Base types of domains, and whether they are arrays of it. Domains
+    /// with a newtype are found in `catalog_types` first.
+    domains: HashMap<(String, String), (Identifier, bool)>,
+    enums: HashSet<(String, String)>,
+    /// Attribute types of the composite types with a generated struct, and
+    /// whether they are arrays of it.
+    composites: HashMap<(String, String), Vec<(Identifier, bool)>>,
+}
 
 impl TypeMapper {
+    pub fn new(catalog: &Catalog, overrides: Vec<TypeOverride>, domain_newtypes: bool) -> Self {
+        let mut catalog_types = HashMap::new();
+        let mut domains = HashMap::new();
+        let mut enums = HashSet::new();
+        let mut composites = HashMap::new();
+        for schema in &catalog.schemas {
+            for enum_def in &schema.enums {
+                enums.insert((schema.name.clone(), enum_def.name.clone()));
+                catalog_types.insert(
+                    (schema.name.clone(), enum_def.name.clone()),
+                    ident::pascal_case(&enum_def.name),
+                );
+            }
+            for composite in &schema.composite_types {
+                if !composite.columns.is_empty() {
+                    let key = (schema.name.clone(), composite.name.clone());
+                    catalog_types.insert(key.clone(), ident::pascal_case(&composite.name));
+                    let attributes = composite
+                        .columns
+                        .iter()
+                        .filter_map(|column| Some((column.r#type.clone()?, column.is_array)))
+                        .collect();
+                    composites.insert(key, attributes);
+                }
+            }
+            for domain in &schema.domains {
+                let key = (schema.name.clone(), domain.name.clone());
+                if domain_newtypes {
+                    catalog_types.insert(key.clone(), ident::pascal_case(&domain.name));
+                }
+                domains.insert(key, (domain.base_type.clone(), domain.is_array));
+            }
+        }
+
+        Self {
+            default_schema: catalog.default_schema.clone(),
+            overrides,
+            catalog_types,
+            domains,
+            enums,
+            composites,
+        }
+    }
+
+    pub fn overrides(&self) -> &[TypeOverride] {
+        &self.overrides
+    }
+
     pub fn sql_to_rust_type(
+        &self,
         sql_type: &Option<Identifier>,
         not_null: bool,
         is_array: bool,
     ) -> String {
         let base_type = match sql_type {
-            Some(identifier) => Self::map_sql_type(&identifier.name),
+            Some(identifier) => self.named_type(identifier),
             None => "String".to_string(),
         };
 
+        Self::wrap_type(base_type, not_null, is_array)
+    }
+
+    /// The Rust type of a domain's base type, which its newtype wraps.
+    pub fn domain_base_type(&self, domain: &Domain) -> String {
+        Self::wrap_type(self.named_type(&domain.base_type), true, domain.is_array)
+    }
+
+    /// The Rust type for a named SQL type, resolving catalog types and
+    /// domains before the built-in mapping.
+    fn named_type(&self, sql_type: &Identifier) -> String {
+        let key = self.type_key(sql_type);
+
+        if let Some(rust_type) = self.catalog_types.get(&key) {
+            return rust_type.clone();
+        }
+        if let Some((base_type, is_array)) = self.domains.get(&key) {
+            return Self::wrap_type(self.named_type(base_type), true, *is_array);
+        }
+        Self::map_sql_type(&sql_type.name)
+    }
+
+    /// The Rust type of a column, taking `overrides` into account. Column
+    /// matchers win over `db_type` ones, and for nullable columns a
+    /// `nullable` override, used as is, wins over wrapping a plain one in
+    /// `Option`. `table` names the table of catalog columns, which don't
+    /// carry it themselves.
+    pub fn column_type(
+        &self,
+        table: Option<&Identifier>,
+        column: &Column,
+        not_null: bool,
+        is_array: bool,
+    ) -> String {
+        match self.find_override(table, column, not_null) {
+            Some((o, not_null)) => Self::wrap_type(o.rust_type.clone(), not_null, is_array),
+            None => self.sql_to_rust_type(&column.r#type, not_null, is_array),
+        }
+    }
+
+    /// The override applying to a column, if any, and whether its type is
+    /// used without wrapping it in `Option`.
+    fn find_override(
+        &self,
+        table: Option<&Identifier>,
+        column: &Column,
+        not_null: bool,
+    ) -> Option<(&TypeOverride, bool)> {
+        let table = column.table.as_ref().or(table);
+        let column_name = match column.original_name.as_deref() {
+            Some(name) if !name.is_empty() => name,
+            _ => &column.name,
+        };
+        let matchers: [&dyn Fn(&TypeOverride) -> bool; 2] = [
+            &|o| o.matches_column(&self.default_schema, table, column_name),
+            &|o| o.matches_db_type(column.r#type.as_ref()),
+        ];
+
+        for matches in matchers {
+            let candidates = || self.overrides.iter().filter(|o| matches(o));
+            let found = match candidates().find(|o| o.nullable && !not_null) {
+                Some(o) => Some((o, true)),
+                None => candidates().find(|o| !o.nullable).map(|o| (o, not_null)),
+            };
+            if found.is_some() {
+                return found;
+            }
+        }
+        None
+    }
+
+    /// The type name of a multirange column that no override maps. sqlx 0.7
+    /// can't decode or encode multiranges, and to sqlx the `Vec<PgRange<T>>`
+    /// they map to is an array of ranges, a different type.
+    pub fn unmapped_multirange<'c>(
+        &self,
+        table: Option<&Identifier>,
+        column: &'c Column,
+    ) -> Option<&'c str> {
+        let name = column.r#type.as_ref()?.name.as_str();
+        // Looking up a nullable column finds overrides of either kind
+        let overridden = self.find_override(table, column, false).is_some();
+        (name.to_lowercase().ends_with("multirange") && !overridden).then_some(name)
+    }
+
+    /// The type name of a column that no override maps and whose values sqlx
+    /// encodes with the OID of a catalog type, which is looked up by name
+    /// only when binding. That is an array of enums or composites, or a
+    /// composite with such an attribute, and binary `COPY` would send them
+    /// with an OID of 0.
+    pub fn unresolved_copy_oid<'c>(
+        &self,
+        table: Option<&Identifier>,
+        column: &'c Column,
+    ) -> Option<&'c str> {
+        let sql_type = column.r#type.as_ref()?;
+        let overridden = self.find_override(table, column, false).is_some();
+        (!overridden && self.encodes_catalog_oid(sql_type, column.is_array, false))
+            .then_some(sql_type.name.as_str())
+    }
+
+    /// Whether encoding a value writes the OID of an enum or composite: for
+    /// array elements, composite attributes, and composites with either.
+    fn encodes_catalog_oid(&self, sql_type: &Identifier, is_array: bool, nested: bool) -> bool {
+        let key = self.type_key(sql_type);
+        if self.enums.contains(&key) {
+            return is_array || nested;
+        }
+        if let Some(attributes) = self.composites.get(&key) {
+            return is_array
+                || nested
+                || attributes.iter().any(|(attribute_type, array)| {
+                    self.encodes_catalog_oid(attribute_type, *array, true)
+                });
+        }
+        match self.domains.get(&key) {
+            Some((base_type, domain_array)) => {
+                self.encodes_catalog_oid(base_type, is_array || *domain_array, nested)
+            }
+            None => false,
+        }
+    }
+
+    /// A type's key in the catalog maps, in the default schema when it isn't
+    /// qualified.
+    fn type_key(&self, sql_type: &Identifier) -> (String, String) {
+        let schema = if sql_type.schema.is_empty() {
+            &self.default_schema
+        } else {
+            &sql_type.schema
+        };
+        (schema.clone(), sql_type.name.clone())
+    }
+
+    fn wrap_type(base_type: String, not_null: bool, is_array: bool) -> String {
         let wrapped_type = if is_array {
             format!("Vec<{base_type}>")
         } else {
@@ -72,6 +271,23 @@ impl TypeMapper {
             // Geometric types (simplified)
             "point" | "line" | "lseg" | "box" | "path" | "polygon" | "circle" => "String",
 
+            // Range types
+            "int4range" => return Self::range_type("int4"),
+            "int8range" => return Self::range_type("int8"),
+            "numrange" => return Self::range_type("numeric"),
+            "tsrange" => return Self::range_type("timestamp"),
+            "tstzrange" => return Self::range_type("timestamptz"),
+            "daterange" => return Self::range_type("date"),
+
+            // A multirange is a set of non-overlapping ranges. Queries can't
+            // use them, see `unmapped_multirange`, so only table models do.
+            "int4multirange" => return format!("Vec<{}>", Self::range_type("int4")),
+            "int8multirange" => return format!("Vec<{}>", Self::range_type("int8")),
+            "nummultirange" => return format!("Vec<{}>", Self::range_type("numeric")),
+            "tsmultirange" => return format!("Vec<{}>", Self::range_type("timestamp")),
+            "tstzmultirange" => return format!("Vec<{}>", Self::range_type("timestamptz")),
+            "datemultirange" => return format!("Vec<{}>", Self::range_type("date")),
+
             // Array types are handled in the caller
             _ if sql_type.ends_with("[]") => {
                 let element_type = &sql_type[..sql_type.len() - 2];
@@ -84,6 +300,13 @@ impl TypeMapper {
         .to_string()
     }
 
+    fn range_type(element_type: &str) -> String {
+        format!(
+            "sqlx::postgres::types::PgRange<{}>",
+            Self::map_sql_type(element_type)
+        )
+    }
+
     pub fn get_rust_imports() -> Vec<&'static str> {
         vec![
             "use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};",
@@ -96,7 +319,288 @@ impl TypeMapper {
     pub fn get_sqlx_imports() -> Vec<&'static str> {
         vec![
             "use sqlx::{FromRow, Row, Pool, Postgres, Error as SqlxError};",
-            "use sqlx::postgres::PgRow;",
+            "use sqlx::postgres::{PgQueryResult, PgRow};",
         ]
     }
 }
+
+impl TypeOverride {
+    /// Checks that the override has exactly one well-formed matcher.
+    pub fn validate(&self) -> Result<()> {
+        match (&self.db_type, &self.column) {
+            (Some(_), None) => {}
+            (None, Some(column)) => {
+                let parts = column.split('.').count();
+                if !(2..=3).contains(&parts) || column.split('.').any(str::is_empty) {
+                    bail!("override column '{column}' must be table.column or schema.table.column");
+                }
+            }
+            _ => bail!(
+                "override for {} must set exactly one of db_type and column",
+                self.rust_type
+            ),
+        }
+        if self.rust_type.trim().is_empty() {
+            bail!("override rust_type must not be empty");
+        }
+        Ok(())
+    }
+
+    /// `db_type` matches the type name, optionally qualified by its schema,
+    /// case-insensitively.
+    fn matches_db_type(&self, sql_type: Option<&Identifier>) -> bool {
+        let (Some(db_type), Some(sql_type)) = (&self.db_type, sql_type) else {
+            return false;
+        };
+        let db_type = db_type.to_lowercase();
+        match db_type.split_once('.') {
+            Some((schema, name)) => {
+                schema == sql_type.schema.to_lowercase() && name == sql_type.name.to_lowercase()
+            }
+            None => db_type == sql_type.name.to_lowercase(),
+        }
+    }
+
+    /// `column` is `table.column` in the default schema or
+    /// `schema.table.column`.
+    fn matches_column(
+        &self,
+        default_schema: &str,
+        table: Option<&Identifier>,
+        column_name: &str,
+    ) -> bool {
+        let (Some(matcher), Some(table)) = (&self.column, table) else {
+            return false;
+        };
+        let schema = if table.schema.is_empty() {
+            default_schema
+        } else {
+            &table.schema
+        };
+
+        let parts: Vec<&str> = matcher.split('.').collect();
+        match parts.as_slice() {
+            [t, c] => schema == default_schema && *t == table.name && *c == column_name,
+            [s, t, c] => *s == schema && *t == table.name && *c == column_name,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn type_override(value: serde_json::Value) -> TypeOverride {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn identifier(schema: &str, name: &str) -> Identifier {
+        Identifier {
+            catalog: String::new(),
+            schema: schema.to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    fn column(name: &str, sql_type: &str, table: Option<Identifier>) -> Column {
+        let mut column: Column = serde_json::from_value(serde_json::json!({
+            "name": name,
+            "not_null": true,
+            "is_array": false,
+            "comment": null,
+            "length": null,
+            "is_named_param": false,
+            "is_func_call": false,
+            "scope": null,
+            "table": null,
+            "table_alias": null,
+            "type": { "catalog": "", "schema": "pg_catalog", "name": sql_type },
+            "is_sqlc_slice": false,
+            "embed_table": null
+        }))
+        .unwrap();
+        column.table = table;
+        column
+    }
+
+    fn mapper(overrides: serde_json::Value) -> TypeMapper {
+        let catalog: Catalog = serde_json::from_value(serde_json::json!({
+            "comment": "",
+            "default_schema": "public",
+            "name": "",
+            "schemas": []
+        }))
+        .unwrap();
+        TypeMapper::new(&catalog, serde_json::from_value(overrides).unwrap(), false)
+    }
+
+    #[test]
+    fn validates_matchers() {
+        let valid = [
+            serde_json::json!({ "db_type": "uuid", "rust_type": "String" }),
+            serde_json::json!({ "column": "users.id", "rust_type": "UserId" }),
+            serde_json::json!({ "column": "auth.users.id", "rust_type": "UserId" }),
+        ];
+        for value in valid {
+            assert!(type_override(value).validate().is_ok());
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_overrides() {
+        let cases = [
+            (
+                serde_json::json!({ "rust_type": "String" }),
+                "override for String must set exactly one of db_type and column",
+            ),
+            (
+                serde_json::json!({ "db_type": "uuid", "column": "users.id", "rust_type": "String" }),
+                "override for String must set exactly one of db_type and column",
+            ),
+            (
+                serde_json::json!({ "column": "id", "rust_type": "UserId" }),
+                "override column 'id' must be table.column or schema.table.column",
+            ),
+            (
+                serde_json::json!({ "column": "a.b.c.d", "rust_type": "UserId" }),
+                "override column 'a.b.c.d' must be table.column or schema.table.column",
+            ),
+            (
+                serde_json::json!({ "column": "users.", "rust_type": "UserId" }),
+                "override column 'users.' must be table.column or schema.table.column",
+            ),
+            (
+                serde_json::json!({ "db_type": "uuid", "rust_type": " " }),
+                "override rust_type must not be empty",
+            ),
+        ];
+        for (value, message) in cases {
+            let err = type_override(value).validate().unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn matches_db_type_case_insensitively() {
+        let plain = type_override(serde_json::json!({ "db_type": "UUID", "rust_type": "String" }));
+        assert!(plain.matches_db_type(Some(&identifier("pg_catalog", "uuid"))));
+        assert!(plain.matches_db_type(Some(&identifier("", "uuid"))));
+        assert!(!plain.matches_db_type(Some(&identifier("pg_catalog", "text"))));
+        assert!(!plain.matches_db_type(None));
+
+        let qualified = type_override(serde_json::json!({
+            "db_type": "auth.role",
+            "rust_type": "Role"
+        }));
+        assert!(qualified.matches_db_type(Some(&identifier("Auth", "role"))));
+        assert!(!qualified.matches_db_type(Some(&identifier("public", "role"))));
+        assert!(!qualified.matches_db_type(Some(&identifier("", "role"))));
+    }
+
+    #[test]
+    fn matches_table_column_in_the_default_schema() {
+        let o = type_override(serde_json::json!({ "column": "users.id", "rust_type": "UserId" }));
+        assert!(o.matches_column("public", Some(&identifier("public", "users")), "id"));
+        assert!(o.matches_column("public", Some(&identifier("", "users")), "id"));
+        assert!(!o.matches_column("public", Some(&identifier("auth", "users")), "id"));
+        assert!(!o.matches_column("public", Some(&identifier("public", "users")), "name"));
+        assert!(!o.matches_column("public", None, "id"));
+    }
+
+    #[test]
+    fn matches_schema_table_column() {
+        let o = type_override(serde_json::json!({
+            "column": "auth.users.id",
+            "rust_type": "UserId"
+        }));
+        assert!(o.matches_column("public", Some(&identifier("auth", "users")), "id"));
+        assert!(!o.matches_column("public", Some(&identifier("public", "users")), "id"));
+        assert!(!o.matches_column("public", Some(&identifier("", "users")), "id"));
+
+        let public = type_override(serde_json::json!({
+            "column": "public.users.id",
+            "rust_type": "UserId"
+        }));
+        assert!(public.matches_column("public", Some(&identifier("", "users")), "id"));
+    }
+
+    #[test]
+    fn column_overrides_beat_db_type_overrides() {
+        let mapper = mapper(serde_json::json!([
+            { "db_type": "int4", "rust_type": "i64" },
+            { "column": "users.id", "rust_type": "UserId" }
+        ]));
+        let users = identifier("public", "users");
+
+        let id = column("id", "int4", Some(users.clone()));
+        assert_eq!(mapper.column_type(None, &id, true, false), "UserId");
+        let other = column("age", "int4", Some(users.clone()));
+        assert_eq!(mapper.column_type(None, &other, true, false), "i64");
+
+        // Catalog columns take their table from the caller
+        let catalog_id = column("id", "int4", None);
+        assert_eq!(
+            mapper.column_type(Some(&users), &catalog_id, true, false),
+            "UserId"
+        );
+        assert_eq!(mapper.column_type(None, &catalog_id, true, false), "i64");
+    }
+
+    #[test]
+    fn matches_the_original_column_name() {
+        let mapper = mapper(serde_json::json!([
+            { "column": "users.id", "rust_type": "UserId" }
+        ]));
+        let mut aliased = column("user_id", "int4", Some(identifier("public", "users")));
+        aliased.original_name = Some("id".to_string());
+        assert_eq!(mapper.column_type(None, &aliased, true, false), "UserId");
+    }
+
+    #[test]
+    fn nullable_overrides_apply_to_nullable_columns() {
+        let mapper = mapper(serde_json::json!([
+            { "db_type": "text", "rust_type": "Name" },
+            { "db_type": "text", "rust_type": "MaybeName", "nullable": true }
+        ]));
+        let name = column("name", "text", None);
+
+        assert_eq!(mapper.column_type(None, &name, true, false), "Name");
+        assert_eq!(mapper.column_type(None, &name, false, false), "MaybeName");
+        assert_eq!(
+            mapper.column_type(None, &name, false, true),
+            "Vec<MaybeName>"
+        );
+
+        let (found, not_null) = mapper.find_override(None, &name, false).unwrap();
+        assert_eq!((found.rust_type.as_str(), not_null), ("MaybeName", true));
+    }
+
+    #[test]
+    fn plain_overrides_are_wrapped_for_nullable_columns() {
+        let mapper = mapper(serde_json::json!([
+            { "db_type": "text", "rust_type": "Name" }
+        ]));
+        let name = column("name", "text", None);
+
+        assert_eq!(
+            mapper.column_type(None, &name, false, false),
+            "Option<Name>"
+        );
+        let (found, not_null) = mapper.find_override(None, &name, false).unwrap();
+        assert_eq!((found.rust_type.as_str(), not_null), ("Name", false));
+        assert!(mapper
+            .find_override(None, &column("id", "int4", None), true)
+            .is_none());
+    }
+
+    #[test]
+    fn nullable_overrides_ignore_not_null_columns() {
+        let mapper = mapper(serde_json::json!([
+            { "db_type": "text", "rust_type": "MaybeName", "nullable": true }
+        ]));
+        let name = column("name", "text", None);
+        assert!(mapper.find_override(None, &name, true).is_none());
+        assert_eq!(mapper.column_type(None, &name, true, false), "String");
+    }
+}
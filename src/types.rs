@@ -1,3 +1,11 @@
+//! The request and response types the generator works on.
+//!
+//! With `format: json` sqlc sends the `GenerateRequest` as protojson, so the
+//! serde representation follows it: fields use their proto names, `bytes`
+//! fields are base64 strings, and unset strings and numbers are sent as
+//! empty values rather than left out. The helpers in [`protojson`] map those
+//! onto the same values [`crate::proto`] produces for protobuf requests.
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Deserialize)]
@@ -6,7 +14,9 @@ pub struct GenerateRequest {
     pub catalog: Catalog,
     pub queries: Vec<Query>,
     pub sqlc_version: String,
+    #[serde(default, deserialize_with = "protojson::json_bytes_or_null")]
     pub plugin_options: serde_json::Value,
+    #[serde(default, deserialize_with = "protojson::json_bytes")]
     pub global_options: Option<serde_json::Value>,
 }
 
@@ -18,6 +28,7 @@ pub struct GenerateResponse {
 #[derive(Debug, Serialize)]
 pub struct File {
     pub name: String,
+    #[serde(serialize_with = "protojson::serialize_bytes")]
     pub contents: Vec<u8>,
 }
 
@@ -27,18 +38,38 @@ pub struct Settings {
     pub engine: String,
     pub schema: Vec<String>,
     pub queries: Vec<String>,
-    pub codegen: Vec<Codegen>,
+    #[serde(default)]
+    pub codegen: Option<Codegen>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Codegen {
     pub out: String,
     pub plugin: String,
+    #[serde(default, deserialize_with = "protojson::json_bytes")]
     pub options: Option<serde_json::Value>,
+    #[serde(default)]
+    pub env: Vec<String>,
+    #[serde(default)]
+    pub process: Option<Process>,
+    #[serde(default)]
+    pub wasm: Option<Wasm>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Process {
+    pub cmd: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Wasm {
+    pub url: String,
+    pub sha256: String,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Catalog {
+    #[serde(default, deserialize_with = "protojson::non_empty")]
     pub comment: Option<String>,
     pub default_schema: String,
     pub name: String,
@@ -47,17 +78,23 @@ pub struct Catalog {
 
 #[derive(Debug, Deserialize)]
 pub struct Schema {
+    #[serde(default, deserialize_with = "protojson::non_empty")]
     pub comment: Option<String>,
     pub name: String,
     pub tables: Vec<Table>,
     pub enums: Vec<Enum>,
     pub composite_types: Vec<CompositeType>,
+    /// sqlc doesn't report domains, so these come from the `domains` plugin
+    /// option.
+    #[serde(skip)]
+    pub domains: Vec<Domain>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Table {
     pub rel: Identifier,
     pub columns: Vec<Column>,
+    #[serde(default, deserialize_with = "protojson::non_empty")]
     pub comment: Option<String>,
 }
 
@@ -66,19 +103,29 @@ pub struct Column {
     pub name: String,
     pub not_null: bool,
     pub is_array: bool,
+    #[serde(default, deserialize_with = "protojson::non_empty")]
     pub comment: Option<String>,
+    #[serde(default, deserialize_with = "protojson::positive")]
     pub length: Option<i32>,
     pub is_named_param: bool,
     pub is_func_call: bool,
+    #[serde(default, deserialize_with = "protojson::non_empty")]
     pub scope: Option<String>,
     pub table: Option<Identifier>,
+    #[serde(default, deserialize_with = "protojson::non_empty")]
     pub table_alias: Option<String>,
     pub r#type: Option<Identifier>,
     pub is_sqlc_slice: bool,
     pub embed_table: Option<Identifier>,
+    #[serde(default, deserialize_with = "protojson::non_empty")]
+    pub original_name: Option<String>,
+    #[serde(default)]
+    pub unsigned: bool,
+    #[serde(default)]
+    pub array_dims: i32,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Identifier {
     pub catalog: String,
     pub schema: String,
@@ -89,13 +136,28 @@ pub struct Identifier {
 pub struct Enum {
     pub name: String,
     pub vals: Vec<String>,
+    #[serde(default, deserialize_with = "protojson::non_empty")]
+    pub comment: Option<String>,
+}
+
+/// A `CREATE DOMAIN` type over `base_type`.
+#[derive(Debug)]
+pub struct Domain {
+    pub name: String,
     pub comment: Option<String>,
+    pub base_type: Identifier,
+    pub is_array: bool,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct CompositeType {
     pub name: String,
+    #[serde(default, deserialize_with = "protojson::non_empty")]
     pub comment: Option<String>,
+    /// The composite's attributes, in order. sqlc only reports the name, so
+    /// these come from the `composite_types` plugin option.
+    #[serde(skip)]
+    pub columns: Vec<Column>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -107,6 +169,8 @@ pub struct Query {
     pub params: Vec<Parameter>,
     pub comments: Vec<String>,
     pub filename: String,
+    #[serde(default)]
+    pub insert_into_table: Option<Identifier>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -130,11 +194,18 @@ pub struct PluginOptions {
     pub emit_methods_with_db_argument: Option<bool>,
     pub emit_enum_valid_method: Option<bool>,
     pub emit_all_enum_values: Option<bool>,
+    pub emit_optional_one: Option<bool>,
+    pub emit_stream_methods: Option<bool>,
+    pub emit_mock: Option<bool>,
+    pub normalize_sql_whitespace: Option<bool>,
+    pub emit_domain_newtypes: Option<bool>,
     pub json_tags_case_style: Option<String>,
     pub output_batch_file_name: Option<String>,
     pub output_db_file_name: Option<String>,
     pub output_models_file_name: Option<String>,
     pub output_querier_file_name: Option<String>,
+    pub output_mock_file_name: Option<String>,
+    pub mock_cfg: Option<String>,
     pub output_files_suffix: Option<String>,
     pub inflection_exclude_table_names: Option<Vec<String>>,
     pub query_parameter_limit: Option<i32>,
@@ -143,4 +214,97 @@ pub struct PluginOptions {
     pub build_tags: Option<String>,
     pub sql_package: Option<String>,
     pub sql_driver: Option<String>,
+    pub overrides: Option<Vec<TypeOverride>>,
+    pub composite_types: Option<Vec<CompositeTypeDefinition>>,
+    pub domains: Option<Vec<DomainDefinition>>,
+}
+
+/// Replaces the Rust type generated for a database type or for a single
+/// `schema.table.column`.
+#[derive(Debug, Deserialize)]
+pub struct TypeOverride {
+    pub db_type: Option<String>,
+    pub column: Option<String>,
+    pub rust_type: String,
+    #[serde(default)]
+    pub nullable: bool,
+    #[serde(default)]
+    pub imports: Vec<String>,
+}
+
+/// The attributes of a composite type in the catalog, which sqlc doesn't
+/// report. `name` is the type's name, or `schema.name` outside the default
+/// schema.
+#[derive(Debug, Deserialize)]
+pub struct CompositeTypeDefinition {
+    pub name: String,
+    pub attributes: Vec<AttributeDefinition>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AttributeDefinition {
+    pub name: String,
+    pub db_type: String,
+    #[serde(default)]
+    pub is_array: bool,
+}
+
+/// A `CREATE DOMAIN` type, which sqlc doesn't report. `name` and `base_type`
+/// are type names, or `schema.name` outside the default schema.
+#[derive(Debug, Deserialize)]
+pub struct DomainDefinition {
+    pub name: String,
+    pub base_type: String,
+    #[serde(default)]
+    pub is_array: bool,
+    pub comment: Option<String>,
+}
+
+/// Serde helpers for the protojson encoding of [`GenerateRequest`] and
+/// [`GenerateResponse`].
+mod protojson {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    /// A `bytes` field holding JSON, such as the plugin options. Empty bytes
+    /// are `None`, as an unset field is.
+    pub fn json_bytes<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<serde_json::Value>, D::Error> {
+        let Some(encoded) = Option::<String>::deserialize(deserializer)? else {
+            return Ok(None);
+        };
+        let bytes = STANDARD.decode(encoded).map_err(D::Error::custom)?;
+        if bytes.is_empty() {
+            return Ok(None);
+        }
+        serde_json::from_slice(&bytes)
+            .map(Some)
+            .map_err(|err| D::Error::custom(format!("options are not valid JSON: {err}")))
+    }
+
+    /// [`json_bytes`] with empty bytes as `null`.
+    pub fn json_bytes_or_null<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<serde_json::Value, D::Error> {
+        json_bytes(deserializer).map(Option::unwrap_or_default)
+    }
+
+    pub fn serialize_bytes<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    /// A string field, where protojson sends an empty string when unset.
+    pub fn non_empty<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<String>, D::Error> {
+        Ok(Option::<String>::deserialize(deserializer)?.filter(|value| !value.is_empty()))
+    }
+
+    /// A number field, where protojson sends `0` when unset.
+    pub fn positive<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<i32>, D::Error> {
+        Ok(Option::<i32>::deserialize(deserializer)?.filter(|value| *value > 0))
+    }
 }
@@ -0,0 +1,97 @@
+//! `:batchexec`, `:batchone` and `:batchmany` methods.
+//!
+//! The generated code for the fixture is checked in next to it and compiled
+//! as part of this test, so a callback or item type that doesn't line up
+//! with the rows fails the build rather than a user's.
+
+use sqlc_gen_rust::{generate_code, GenerateRequest};
+
+#[rustfmt::skip]
+#[allow(dead_code, unused_imports)]
+#[path = "corpus/batch/users/models.rs"]
+mod models;
+
+#[rustfmt::skip]
+#[allow(dead_code, unused_imports)]
+#[path = "corpus/batch/users/queries.rs"]
+mod queries;
+
+#[rustfmt::skip]
+#[allow(dead_code, unused_imports)]
+#[path = "corpus/batch/users/batch.rs"]
+mod batch;
+
+use queries::{AddUserParams, Database, PostsByUserRow, RenameUserParams, RenameUserRow};
+use sqlx::Error as SqlxError;
+
+fn corpus(path: &str) -> String {
+    let path = format!("{}/tests/corpus/batch/{path}", env!("CARGO_MANIFEST_DIR"));
+    std::fs::read_to_string(&path).unwrap()
+}
+
+#[test]
+fn compiled_batch_code_is_up_to_date() {
+    let request: GenerateRequest = serde_json::from_str(&corpus("users.json")).unwrap();
+    let response = generate_code(request).unwrap();
+
+    for name in ["models.rs", "queries.rs", "batch.rs"] {
+        let file = response
+            .files
+            .iter()
+            .find(|file| file.name == name)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(file.contents.clone()).unwrap(),
+            corpus(&format!("users/{name}")),
+            "tests/corpus/batch/users/{name} is out of date"
+        );
+    }
+}
+
+#[test]
+fn batches_run_as_one_statement_over_arrays() {
+    for (sql, arrays) in [
+        (queries::ADD_USER, "unnest($1::text[], $2::text[])"),
+        (queries::USER_NAME, "unnest($1::int4[])"),
+        (queries::RENAME_USER, "unnest($1::int4[], $2::text[])"),
+        (queries::POSTS_BY_USER, "unnest($1::int4[])"),
+    ] {
+        assert!(
+            sql.contains(&format!("{arrays} WITH ORDINALITY AS sqlc_batch")),
+            "{sql}"
+        );
+    }
+}
+
+/// Only type-checked, it needs a database to run.
+#[allow(dead_code)]
+async fn runs_batches(db: &Database) -> Result<(), SqlxError> {
+    let users = vec![
+        AddUserParams {
+            name: "a".to_string(),
+            email: None,
+        },
+        AddUserParams {
+            name: "b".to_string(),
+            email: Some("b@example.com".to_string()),
+        },
+    ];
+    let mut added = 0;
+    db.add_user(users, |_, ()| added += 1).await?;
+
+    let mut names = Vec::new();
+    db.user_name([1, 2], |i, name: String| names.push((i, name)))
+        .await?;
+
+    let mut posts: Vec<Vec<PostsByUserRow>> = Vec::new();
+    db.posts_by_user([1, 2], |_, rows| posts.push(rows)).await?;
+
+    let mut tx = db.begin().await?;
+    let renames = names.into_iter().map(|(i, name)| RenameUserParams {
+        id: i as i32 + 1,
+        name: name.to_uppercase(),
+    });
+    let mut renamed: Vec<RenameUserRow> = Vec::new();
+    tx.rename_user(renames, |_, row| renamed.push(row)).await?;
+    tx.commit().await
+}
@@ -0,0 +1,79 @@
+//! `:copyfrom` methods, with an item struct and with a single column.
+//!
+//! The generated code for the fixture is checked in next to it and compiled
+//! as part of this test, so a field that has no sqlx `Encode` impl or an
+//! item type that doesn't match fails the build rather than a user's.
+
+use sqlc_gen_rust::{generate_code, GenerateRequest};
+
+#[rustfmt::skip]
+#[allow(dead_code, unused_imports)]
+#[path = "corpus/copyfrom/events/models.rs"]
+mod models;
+
+#[rustfmt::skip]
+#[allow(dead_code, unused_imports)]
+#[path = "corpus/copyfrom/events/queries.rs"]
+mod queries;
+
+use models::EventKind;
+use queries::{Database, InsertEventsParams};
+use sqlx::Error as SqlxError;
+
+fn corpus(path: &str) -> String {
+    let path = format!(
+        "{}/tests/corpus/copyfrom/{path}",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    std::fs::read_to_string(&path).unwrap()
+}
+
+#[test]
+fn compiled_copyfrom_code_is_up_to_date() {
+    let request: GenerateRequest = serde_json::from_str(&corpus("events.json")).unwrap();
+    let response = generate_code(request).unwrap();
+
+    for name in ["models.rs", "queries.rs"] {
+        let file = response
+            .files
+            .iter()
+            .find(|file| file.name == name)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(file.contents.clone()).unwrap(),
+            corpus(&format!("events/{name}")),
+            "tests/corpus/copyfrom/events/{name} is out of date"
+        );
+    }
+}
+
+#[test]
+fn copies_the_inserted_columns_in_binary() {
+    assert_eq!(
+        queries::INSERT_EVENTS,
+        r#"COPY "public"."events" ("kind", "session_id", "tags", "happened_at") FROM STDIN (FORMAT binary)"#
+    );
+    assert_eq!(
+        queries::INSERT_EVENT_KINDS,
+        r#"COPY "public"."events" ("kind") FROM STDIN (FORMAT binary)"#
+    );
+}
+
+/// Only type-checked, it needs a database to run.
+#[allow(dead_code)]
+async fn copies_events(db: &Database) -> Result<u64, SqlxError> {
+    let events = (0..3).map(|i| InsertEventsParams {
+        kind: EventKind::Click,
+        session_id: None,
+        tags: vec![format!("tag {i}")],
+        happened_at: chrono::Utc::now(),
+    });
+    let mut copied = db.insert_events(events).await?;
+
+    let mut tx = db.begin().await?;
+    copied += tx
+        .insert_event_kinds([EventKind::View, EventKind::Click])
+        .await?;
+    tx.commit().await?;
+    Ok(copied)
+}
@@ -0,0 +1,280 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use rust_decimal::Decimal;
+use sqlx::{FromRow, Row, Pool, Postgres, Error as SqlxError};
+use sqlx::postgres::{PgQueryResult, PgRow};
+use crate::models::*;
+use crate::queries::*;
+
+impl Database {
+    pub async fn add_user(
+        &self,
+        items: impl IntoIterator<Item = AddUserParams>,
+        mut f: impl FnMut(usize, ()),
+    ) -> Result<(), SqlxError> {
+        let mut name_values = Vec::new();
+        let mut email_values = Vec::new();
+        for params in items {
+            name_values.push(params.name);
+            email_values.push(params.email);
+        }
+        let count = name_values.len();
+        if count == 0 {
+            return Ok(());
+        }
+        sqlx::query(ADD_USER)
+            .bind(name_values)
+            .bind(email_values)
+            .execute(&self.pool)
+            .await?;
+        for i in 0..count {
+            f(i, ());
+        }
+        Ok(())
+    }
+
+    pub async fn user_name(
+        &self,
+        items: impl IntoIterator<Item = i32>,
+        mut f: impl FnMut(usize, String),
+    ) -> Result<(), SqlxError> {
+        let mut id_values = Vec::new();
+        for id in items {
+            id_values.push(id);
+        }
+        let count = id_values.len();
+        if count == 0 {
+            return Ok(());
+        }
+        let rows = sqlx::query(USER_NAME)
+            .bind(id_values)
+            .fetch_all(&self.pool)
+            .await?;
+        let mut results: Vec<_> = (0..count).map(|_| None).collect();
+        for row in &rows {
+            let i = row.try_get::<i64, _>("sqlc_ord")? as usize - 1;
+            if results[i].is_none() {
+                results[i] = Some(row.try_get::<String, _>(0)?);
+            }
+        }
+        let results = results
+            .into_iter()
+            .map(|result| result.ok_or(SqlxError::RowNotFound))
+            .collect::<Result<Vec<_>, _>>()?;
+        for (i, result) in results.into_iter().enumerate() {
+            f(i, result);
+        }
+        Ok(())
+    }
+
+    pub async fn rename_user(
+        &self,
+        items: impl IntoIterator<Item = RenameUserParams>,
+        mut f: impl FnMut(usize, RenameUserRow),
+    ) -> Result<(), SqlxError> {
+        let mut id_values = Vec::new();
+        let mut name_values = Vec::new();
+        for params in items {
+            id_values.push(params.id);
+            name_values.push(params.name);
+        }
+        let count = id_values.len();
+        if count == 0 {
+            return Ok(());
+        }
+        let mut tx = self.pool.begin().await?;
+        let rows = sqlx::query(RENAME_USER)
+            .bind(id_values)
+            .bind(name_values)
+            .fetch_all(&mut *tx)
+            .await?;
+        let mut results: Vec<_> = (0..count).map(|_| None).collect();
+        for row in &rows {
+            let i = row.try_get::<i64, _>("sqlc_ord")? as usize - 1;
+            if results[i].is_none() {
+                results[i] = Some(RenameUserRow {
+                    id: row.try_get::<i32, _>(0)?,
+                    name: row.try_get::<String, _>(1)?,
+                });
+            }
+        }
+        let results = results
+            .into_iter()
+            .map(|result| result.ok_or(SqlxError::RowNotFound))
+            .collect::<Result<Vec<_>, _>>()?;
+        tx.commit().await?;
+        for (i, result) in results.into_iter().enumerate() {
+            f(i, result);
+        }
+        Ok(())
+    }
+
+    pub async fn posts_by_user(
+        &self,
+        items: impl IntoIterator<Item = i32>,
+        mut f: impl FnMut(usize, Vec<PostsByUserRow>),
+    ) -> Result<(), SqlxError> {
+        let mut user_id_values = Vec::new();
+        for user_id in items {
+            user_id_values.push(user_id);
+        }
+        let count = user_id_values.len();
+        if count == 0 {
+            return Ok(());
+        }
+        let rows = sqlx::query(POSTS_BY_USER)
+            .bind(user_id_values)
+            .fetch_all(&self.pool)
+            .await?;
+        let mut results: Vec<_> = (0..count).map(|_| Vec::new()).collect();
+        for row in &rows {
+            let i = row.try_get::<i64, _>("sqlc_ord")? as usize - 1;
+            results[i].push(PostsByUserRow {
+                id: row.try_get::<i32, _>(0)?,
+                title: row.try_get::<String, _>(1)?,
+            });
+        }
+        for (i, result) in results.into_iter().enumerate() {
+            f(i, result);
+        }
+        Ok(())
+    }
+
+}
+
+impl DatabaseTx {
+    pub async fn add_user(
+        &mut self,
+        items: impl IntoIterator<Item = AddUserParams>,
+        mut f: impl FnMut(usize, ()),
+    ) -> Result<(), SqlxError> {
+        let mut name_values = Vec::new();
+        let mut email_values = Vec::new();
+        for params in items {
+            name_values.push(params.name);
+            email_values.push(params.email);
+        }
+        let count = name_values.len();
+        if count == 0 {
+            return Ok(());
+        }
+        sqlx::query(ADD_USER)
+            .bind(name_values)
+            .bind(email_values)
+            .execute(&mut *self.tx)
+            .await?;
+        for i in 0..count {
+            f(i, ());
+        }
+        Ok(())
+    }
+
+    pub async fn user_name(
+        &mut self,
+        items: impl IntoIterator<Item = i32>,
+        mut f: impl FnMut(usize, String),
+    ) -> Result<(), SqlxError> {
+        let mut id_values = Vec::new();
+        for id in items {
+            id_values.push(id);
+        }
+        let count = id_values.len();
+        if count == 0 {
+            return Ok(());
+        }
+        let rows = sqlx::query(USER_NAME)
+            .bind(id_values)
+            .fetch_all(&mut *self.tx)
+            .await?;
+        let mut results: Vec<_> = (0..count).map(|_| None).collect();
+        for row in &rows {
+            let i = row.try_get::<i64, _>("sqlc_ord")? as usize - 1;
+            if results[i].is_none() {
+                results[i] = Some(row.try_get::<String, _>(0)?);
+            }
+        }
+        let results = results
+            .into_iter()
+            .map(|result| result.ok_or(SqlxError::RowNotFound))
+            .collect::<Result<Vec<_>, _>>()?;
+        for (i, result) in results.into_iter().enumerate() {
+            f(i, result);
+        }
+        Ok(())
+    }
+
+    pub async fn rename_user(
+        &mut self,
+        items: impl IntoIterator<Item = RenameUserParams>,
+        mut f: impl FnMut(usize, RenameUserRow),
+    ) -> Result<(), SqlxError> {
+        let mut id_values = Vec::new();
+        let mut name_values = Vec::new();
+        for params in items {
+            id_values.push(params.id);
+            name_values.push(params.name);
+        }
+        let count = id_values.len();
+        if count == 0 {
+            return Ok(());
+        }
+        let mut tx = sqlx::Connection::begin(&mut *self.tx).await?;
+        let rows = sqlx::query(RENAME_USER)
+            .bind(id_values)
+            .bind(name_values)
+            .fetch_all(&mut *tx)
+            .await?;
+        let mut results: Vec<_> = (0..count).map(|_| None).collect();
+        for row in &rows {
+            let i = row.try_get::<i64, _>("sqlc_ord")? as usize - 1;
+            if results[i].is_none() {
+                results[i] = Some(RenameUserRow {
+                    id: row.try_get::<i32, _>(0)?,
+                    name: row.try_get::<String, _>(1)?,
+                });
+            }
+        }
+        let results = results
+            .into_iter()
+            .map(|result| result.ok_or(SqlxError::RowNotFound))
+            .collect::<Result<Vec<_>, _>>()?;
+        tx.commit().await?;
+        for (i, result) in results.into_iter().enumerate() {
+            f(i, result);
+        }
+        Ok(())
+    }
+
+    pub async fn posts_by_user(
+        &mut self,
+        items: impl IntoIterator<Item = i32>,
+        mut f: impl FnMut(usize, Vec<PostsByUserRow>),
+    ) -> Result<(), SqlxError> {
+        let mut user_id_values = Vec::new();
+        for user_id in items {
+            user_id_values.push(user_id);
+        }
+        let count = user_id_values.len();
+        if count == 0 {
+            return Ok(());
+        }
+        let rows = sqlx::query(POSTS_BY_USER)
+            .bind(user_id_values)
+            .fetch_all(&mut *self.tx)
+            .await?;
+        let mut results: Vec<_> = (0..count).map(|_| Vec::new()).collect();
+        for row in &rows {
+            let i = row.try_get::<i64, _>("sqlc_ord")? as usize - 1;
+            results[i].push(PostsByUserRow {
+                id: row.try_get::<i32, _>(0)?,
+                title: row.try_get::<String, _>(1)?,
+            });
+        }
+        for (i, result) in results.into_iter().enumerate() {
+            f(i, result);
+        }
+        Ok(())
+    }
+
+}
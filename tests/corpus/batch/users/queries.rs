@@ -0,0 +1,197 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use rust_decimal::Decimal;
+use sqlx::{FromRow, Row, Pool, Postgres, Error as SqlxError};
+use sqlx::postgres::{PgQueryResult, PgRow};
+use crate::models::*;
+
+#[derive(Debug, Clone)]
+pub struct AddUserParams {
+    pub name: String,
+    pub email: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "snake_case")]
+pub struct RenameUserRow {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct RenameUserParams {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "snake_case")]
+pub struct PostsByUserRow {
+    pub id: i32,
+    pub title: String,
+}
+
+pub const ADD_USER: &str = r"INSERT INTO users (name, email) SELECT sqlc_batch.sqlc_p1, sqlc_batch.sqlc_p2
+FROM unnest($1::text[], $2::text[]) WITH ORDINALITY AS sqlc_batch (sqlc_p1, sqlc_p2, sqlc_ord)
+ORDER BY sqlc_batch.sqlc_ord";
+
+pub const USER_NAME: &str = r"SELECT sqlc_q.*, sqlc_batch.sqlc_ord
+FROM unnest($1::int4[]) WITH ORDINALITY AS sqlc_batch (sqlc_p1, sqlc_ord)
+CROSS JOIN LATERAL (
+SELECT sqlc_inner.*, row_number() OVER () AS sqlc_row
+FROM (
+SELECT name FROM users WHERE id = sqlc_batch.sqlc_p1
+) AS sqlc_inner
+) AS sqlc_q
+ORDER BY sqlc_batch.sqlc_ord, sqlc_q.sqlc_row";
+
+pub const RENAME_USER: &str = r"UPDATE users SET name = sqlc_batch.sqlc_p2
+FROM unnest($1::int4[], $2::text[]) WITH ORDINALITY AS sqlc_batch (sqlc_p1, sqlc_p2, sqlc_ord)
+WHERE id = sqlc_batch.sqlc_p1 RETURNING id, name, sqlc_batch.sqlc_ord";
+
+pub const POSTS_BY_USER: &str = r"SELECT sqlc_q.*, sqlc_batch.sqlc_ord
+FROM unnest($1::int4[]) WITH ORDINALITY AS sqlc_batch (sqlc_p1, sqlc_ord)
+CROSS JOIN LATERAL (
+SELECT sqlc_inner.*, row_number() OVER () AS sqlc_row
+FROM (
+SELECT id, title FROM posts WHERE user_id = sqlc_batch.sqlc_p1 ORDER BY id
+) AS sqlc_inner
+) AS sqlc_q
+ORDER BY sqlc_batch.sqlc_ord, sqlc_q.sqlc_row";
+
+pub struct Database {
+    pub(crate) pool: Pool<Postgres>,
+}
+
+impl Database {
+    pub fn new(pool: Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    pub async fn begin(&self) -> Result<DatabaseTx, SqlxError> {
+        let tx = self.pool.begin().await?;
+        Ok(DatabaseTx { tx })
+    }
+
+    pub async fn begin_with(&self, isolation: IsolationLevel) -> Result<DatabaseTx, SqlxError> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(isolation.set_transaction())
+            .execute(&mut *tx)
+            .await?;
+        Ok(DatabaseTx { tx })
+    }
+
+    /// Runs `f` in a transaction that is committed when it returns `Ok` and
+    /// rolled back when it returns `Err`.
+    ///
+    /// ```ignore
+    /// let user = db.transaction(async |tx| {
+    ///     let user = tx.get_user(id).await?;
+    ///     tx.delete_user(user.id).await?;
+    ///     Ok(user)
+    /// }).await?;
+    /// ```
+    pub async fn transaction<T>(
+        &self,
+        f: impl AsyncFnMut(&mut DatabaseTx) -> Result<T, SqlxError>,
+    ) -> Result<T, SqlxError> {
+        self.transaction_with(TransactionOptions::default(), f).await
+    }
+
+    /// Like `transaction`, with the given isolation level. The whole
+    /// transaction is retried, calling `f` again, when it fails with a
+    /// serialization failure, at most `options.max_retries` times.
+    pub async fn transaction_with<T>(
+        &self,
+        options: TransactionOptions,
+        mut f: impl AsyncFnMut(&mut DatabaseTx) -> Result<T, SqlxError>,
+    ) -> Result<T, SqlxError> {
+        let mut retries = 0;
+        loop {
+            let mut tx = match options.isolation {
+                Some(isolation) => self.begin_with(isolation).await?,
+                None => self.begin().await?,
+            };
+            let result = match f(&mut tx).await {
+                Ok(value) => tx.commit().await.map(|()| value),
+                Err(err) => {
+                    // The original error is more useful than a failed rollback
+                    let _ = tx.rollback().await;
+                    Err(err)
+                }
+            };
+            match result {
+                Err(err) if retries < options.max_retries && is_serialization_failure(&err) => {
+                    retries += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+}
+
+/// Isolation level a transaction is started with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IsolationLevel {
+    ReadCommitted,
+    RepeatableRead,
+    Serializable,
+}
+
+impl IsolationLevel {
+    fn set_transaction(self) -> &'static str {
+        match self {
+            IsolationLevel::ReadCommitted => "SET TRANSACTION ISOLATION LEVEL READ COMMITTED",
+            IsolationLevel::RepeatableRead => "SET TRANSACTION ISOLATION LEVEL REPEATABLE READ",
+            IsolationLevel::Serializable => "SET TRANSACTION ISOLATION LEVEL SERIALIZABLE",
+        }
+    }
+}
+
+/// How `Database::transaction_with` runs its closure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransactionOptions {
+    /// Isolation level of the transaction, the server default when `None`.
+    pub isolation: Option<IsolationLevel>,
+    /// How many times the closure is retried after a serialization failure.
+    pub max_retries: u32,
+}
+
+impl Default for TransactionOptions {
+    fn default() -> Self {
+        Self {
+            isolation: None,
+            max_retries: 3,
+        }
+    }
+}
+
+/// A transaction started with `Database::begin`. Dropping it without calling
+/// `commit` rolls it back.
+pub struct DatabaseTx {
+    pub(crate) tx: sqlx::Transaction<'static, Postgres>,
+}
+
+impl DatabaseTx {
+    pub async fn commit(self) -> Result<(), SqlxError> {
+        self.tx.commit().await
+    }
+
+    pub async fn rollback(self) -> Result<(), SqlxError> {
+        self.tx.rollback().await
+    }
+}
+
+/// Whether an error is a serialization failure (SQLSTATE 40001), meaning the
+/// transaction can succeed when retried.
+fn is_serialization_failure(err: &SqlxError) -> bool {
+    match err {
+        SqlxError::Database(err) => err.code().as_deref() == Some("40001"),
+        _ => false,
+    }
+}
+
+impl DatabaseTx {
+}
@@ -0,0 +1,35 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use rust_decimal::Decimal;
+use sqlx::FromRow;
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "snake_case")]
+pub struct Events {
+    pub id: i64,
+    pub kind: EventKind,
+    pub session_id: Option<uuid::Uuid>,
+    pub tags: Vec<String>,
+    pub happened_at: chrono::DateTime<chrono::Utc>,
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "event_kind")]
+pub enum EventKind {
+    #[serde(rename = "click")]
+    #[sqlx(rename = "click")]
+    Click,
+    #[serde(rename = "view")]
+    #[sqlx(rename = "view")]
+    View,
+}
+
+impl sqlx::postgres::PgHasArrayType for EventKind {
+    fn array_type_info() -> sqlx::postgres::PgTypeInfo {
+        sqlx::postgres::PgTypeInfo::with_name("_event_kind")
+    }
+}
+
+
@@ -0,0 +1,279 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use rust_decimal::Decimal;
+use sqlx::{FromRow, Row, Pool, Postgres, Error as SqlxError};
+use sqlx::postgres::{PgQueryResult, PgRow};
+use crate::models::*;
+
+#[derive(Debug, Clone)]
+pub struct InsertEventsParams {
+    pub kind: EventKind,
+    pub session_id: Option<uuid::Uuid>,
+    pub tags: Vec<String>,
+    pub happened_at: chrono::DateTime<chrono::Utc>,
+}
+
+pub const INSERT_EVENTS: &str = r#"COPY "public"."events" ("kind", "session_id", "tags", "happened_at") FROM STDIN (FORMAT binary)"#;
+
+pub const INSERT_EVENT_KINDS: &str = r#"COPY "public"."events" ("kind") FROM STDIN (FORMAT binary)"#;
+
+pub struct Database {
+    pub(crate) pool: Pool<Postgres>,
+}
+
+impl Database {
+    pub fn new(pool: Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    pub async fn begin(&self) -> Result<DatabaseTx, SqlxError> {
+        let tx = self.pool.begin().await?;
+        Ok(DatabaseTx { tx })
+    }
+
+    pub async fn begin_with(&self, isolation: IsolationLevel) -> Result<DatabaseTx, SqlxError> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(isolation.set_transaction())
+            .execute(&mut *tx)
+            .await?;
+        Ok(DatabaseTx { tx })
+    }
+
+    /// Runs `f` in a transaction that is committed when it returns `Ok` and
+    /// rolled back when it returns `Err`.
+    ///
+    /// ```ignore
+    /// let user = db.transaction(async |tx| {
+    ///     let user = tx.get_user(id).await?;
+    ///     tx.delete_user(user.id).await?;
+    ///     Ok(user)
+    /// }).await?;
+    /// ```
+    pub async fn transaction<T>(
+        &self,
+        f: impl AsyncFnMut(&mut DatabaseTx) -> Result<T, SqlxError>,
+    ) -> Result<T, SqlxError> {
+        self.transaction_with(TransactionOptions::default(), f).await
+    }
+
+    /// Like `transaction`, with the given isolation level. The whole
+    /// transaction is retried, calling `f` again, when it fails with a
+    /// serialization failure, at most `options.max_retries` times.
+    pub async fn transaction_with<T>(
+        &self,
+        options: TransactionOptions,
+        mut f: impl AsyncFnMut(&mut DatabaseTx) -> Result<T, SqlxError>,
+    ) -> Result<T, SqlxError> {
+        let mut retries = 0;
+        loop {
+            let mut tx = match options.isolation {
+                Some(isolation) => self.begin_with(isolation).await?,
+                None => self.begin().await?,
+            };
+            let result = match f(&mut tx).await {
+                Ok(value) => tx.commit().await.map(|()| value),
+                Err(err) => {
+                    // The original error is more useful than a failed rollback
+                    let _ = tx.rollback().await;
+                    Err(err)
+                }
+            };
+            match result {
+                Err(err) if retries < options.max_retries && is_serialization_failure(&err) => {
+                    retries += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    pub async fn insert_events(
+        &self,
+        items: impl IntoIterator<Item = InsertEventsParams>,
+    ) -> Result<u64, SqlxError> {
+        let mut conn = self.pool.acquire().await?;
+        let mut copy = conn.copy_in_raw(INSERT_EVENTS).await?;
+
+        // Binary COPY header: signature, flags and header extension length
+        let mut buf = b"PGCOPY\n\xff\r\n\0".to_vec();
+        buf.extend_from_slice(&0i32.to_be_bytes());
+        buf.extend_from_slice(&0i32.to_be_bytes());
+
+        for params in items {
+            buf.extend_from_slice(&4i16.to_be_bytes());
+            encode_copy_field(&mut buf, &params.kind);
+            encode_copy_field(&mut buf, &params.session_id);
+            encode_copy_field(&mut buf, &params.tags);
+            encode_copy_field(&mut buf, &params.happened_at);
+            if buf.len() >= COPY_CHUNK_SIZE {
+                copy.send(std::mem::take(&mut buf)).await?;
+            }
+        }
+
+        // File trailer
+        buf.extend_from_slice(&(-1i16).to_be_bytes());
+        copy.send(buf).await?;
+        copy.finish().await
+    }
+
+    pub async fn insert_event_kinds(
+        &self,
+        items: impl IntoIterator<Item = EventKind>,
+    ) -> Result<u64, SqlxError> {
+        let mut conn = self.pool.acquire().await?;
+        let mut copy = conn.copy_in_raw(INSERT_EVENT_KINDS).await?;
+
+        // Binary COPY header: signature, flags and header extension length
+        let mut buf = b"PGCOPY\n\xff\r\n\0".to_vec();
+        buf.extend_from_slice(&0i32.to_be_bytes());
+        buf.extend_from_slice(&0i32.to_be_bytes());
+
+        for kind in items {
+            buf.extend_from_slice(&1i16.to_be_bytes());
+            encode_copy_field(&mut buf, &kind);
+            if buf.len() >= COPY_CHUNK_SIZE {
+                copy.send(std::mem::take(&mut buf)).await?;
+            }
+        }
+
+        // File trailer
+        buf.extend_from_slice(&(-1i16).to_be_bytes());
+        copy.send(buf).await?;
+        copy.finish().await
+    }
+
+}
+
+/// Isolation level a transaction is started with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IsolationLevel {
+    ReadCommitted,
+    RepeatableRead,
+    Serializable,
+}
+
+impl IsolationLevel {
+    fn set_transaction(self) -> &'static str {
+        match self {
+            IsolationLevel::ReadCommitted => "SET TRANSACTION ISOLATION LEVEL READ COMMITTED",
+            IsolationLevel::RepeatableRead => "SET TRANSACTION ISOLATION LEVEL REPEATABLE READ",
+            IsolationLevel::Serializable => "SET TRANSACTION ISOLATION LEVEL SERIALIZABLE",
+        }
+    }
+}
+
+/// How `Database::transaction_with` runs its closure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransactionOptions {
+    /// Isolation level of the transaction, the server default when `None`.
+    pub isolation: Option<IsolationLevel>,
+    /// How many times the closure is retried after a serialization failure.
+    pub max_retries: u32,
+}
+
+impl Default for TransactionOptions {
+    fn default() -> Self {
+        Self {
+            isolation: None,
+            max_retries: 3,
+        }
+    }
+}
+
+/// A transaction started with `Database::begin`. Dropping it without calling
+/// `commit` rolls it back.
+pub struct DatabaseTx {
+    pub(crate) tx: sqlx::Transaction<'static, Postgres>,
+}
+
+impl DatabaseTx {
+    pub async fn commit(self) -> Result<(), SqlxError> {
+        self.tx.commit().await
+    }
+
+    pub async fn rollback(self) -> Result<(), SqlxError> {
+        self.tx.rollback().await
+    }
+}
+
+/// Whether an error is a serialization failure (SQLSTATE 40001), meaning the
+/// transaction can succeed when retried.
+fn is_serialization_failure(err: &SqlxError) -> bool {
+    match err {
+        SqlxError::Database(err) => err.code().as_deref() == Some("40001"),
+        _ => false,
+    }
+}
+
+impl DatabaseTx {
+    pub async fn insert_events(
+        &mut self,
+        items: impl IntoIterator<Item = InsertEventsParams>,
+    ) -> Result<u64, SqlxError> {
+        let mut copy = self.tx.copy_in_raw(INSERT_EVENTS).await?;
+
+        // Binary COPY header: signature, flags and header extension length
+        let mut buf = b"PGCOPY\n\xff\r\n\0".to_vec();
+        buf.extend_from_slice(&0i32.to_be_bytes());
+        buf.extend_from_slice(&0i32.to_be_bytes());
+
+        for params in items {
+            buf.extend_from_slice(&4i16.to_be_bytes());
+            encode_copy_field(&mut buf, &params.kind);
+            encode_copy_field(&mut buf, &params.session_id);
+            encode_copy_field(&mut buf, &params.tags);
+            encode_copy_field(&mut buf, &params.happened_at);
+            if buf.len() >= COPY_CHUNK_SIZE {
+                copy.send(std::mem::take(&mut buf)).await?;
+            }
+        }
+
+        // File trailer
+        buf.extend_from_slice(&(-1i16).to_be_bytes());
+        copy.send(buf).await?;
+        copy.finish().await
+    }
+
+    pub async fn insert_event_kinds(
+        &mut self,
+        items: impl IntoIterator<Item = EventKind>,
+    ) -> Result<u64, SqlxError> {
+        let mut copy = self.tx.copy_in_raw(INSERT_EVENT_KINDS).await?;
+
+        // Binary COPY header: signature, flags and header extension length
+        let mut buf = b"PGCOPY\n\xff\r\n\0".to_vec();
+        buf.extend_from_slice(&0i32.to_be_bytes());
+        buf.extend_from_slice(&0i32.to_be_bytes());
+
+        for kind in items {
+            buf.extend_from_slice(&1i16.to_be_bytes());
+            encode_copy_field(&mut buf, &kind);
+            if buf.len() >= COPY_CHUNK_SIZE {
+                copy.send(std::mem::take(&mut buf)).await?;
+            }
+        }
+
+        // File trailer
+        buf.extend_from_slice(&(-1i16).to_be_bytes());
+        copy.send(buf).await?;
+        copy.finish().await
+    }
+
+}
+
+const COPY_CHUNK_SIZE: usize = 64 * 1024;
+
+fn encode_copy_field<'q, T>(buf: &mut Vec<u8>, value: &T)
+where
+    T: sqlx::Encode<'q, Postgres>,
+{
+    let mut field = sqlx::postgres::PgArgumentBuffer::default();
+    match value.encode_by_ref(&mut field) {
+        sqlx::encode::IsNull::Yes => buf.extend_from_slice(&(-1i32).to_be_bytes()),
+        sqlx::encode::IsNull::No => {
+            buf.extend_from_slice(&(field.len() as i32).to_be_bytes());
+            buf.extend_from_slice(&field);
+        }
+    }
+}
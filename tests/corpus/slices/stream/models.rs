@@ -0,0 +1,25 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use rust_decimal::Decimal;
+use sqlx::FromRow;
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Users {
+    pub id: i32,
+    pub name: String,
+    pub email: Option<String>,
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+
+/// Blog posts
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Posts {
+    pub id: i32,
+    pub user_id: i32,
+    pub title: String,
+    pub content: Option<String>,
+}
+
+
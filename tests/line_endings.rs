@@ -0,0 +1,65 @@
+//! Query text with carriage returns.
+//!
+//! rustc reads a CRLF inside a raw string literal as a bare newline, so the
+//! generated constants are compiled here and compared with the query text
+//! sqlc sent rather than with the generated source.
+
+use sqlc_gen_rust::{generate_code, GenerateRequest};
+
+#[rustfmt::skip]
+#[allow(dead_code, unused_imports)]
+#[path = "corpus/line_endings/crlf/models.rs"]
+mod models;
+
+#[rustfmt::skip]
+#[allow(dead_code, unused_imports)]
+#[path = "corpus/line_endings/crlf/queries.rs"]
+mod queries;
+
+fn corpus(path: &str) -> String {
+    let path = format!(
+        "{}/tests/corpus/line_endings/{path}",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    std::fs::read_to_string(&path).unwrap()
+}
+
+fn request() -> GenerateRequest {
+    serde_json::from_str(&corpus("crlf.json")).unwrap()
+}
+
+#[test]
+fn compiled_crlf_code_is_up_to_date() {
+    let response = generate_code(request()).unwrap();
+
+    for name in ["models.rs", "queries.rs"] {
+        let file = response
+            .files
+            .iter()
+            .find(|file| file.name == name)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(file.contents.clone()).unwrap(),
+            corpus(&format!("crlf/{name}")),
+            "tests/corpus/line_endings/crlf/{name} is out of date"
+        );
+    }
+}
+
+#[test]
+fn compiled_constants_keep_carriage_returns() {
+    let request = request();
+    let text = |name: &str| {
+        request
+            .queries
+            .iter()
+            .find(|query| query.name == name)
+            .unwrap()
+            .text
+            .clone()
+    };
+
+    assert!(text("GetUser").contains("\r\n"));
+    assert_eq!(queries::GET_USER, text("GetUser"));
+    assert_eq!(queries::COUNT_USERS, text("CountUsers"));
+}
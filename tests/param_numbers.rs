@@ -0,0 +1,70 @@
+//! Regression corpus for binding parameters by their sqlc `$n` number.
+
+use sqlc_gen_rust::{generate_code, GenerateRequest, GenerateResponse};
+
+fn generate(fixture: &str) -> anyhow::Result<GenerateResponse> {
+    let path = format!(
+        "{}/tests/corpus/params/{fixture}",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    let input = std::fs::read_to_string(&path).unwrap();
+    let request: GenerateRequest = serde_json::from_str(&input).unwrap();
+    generate_code(request)
+}
+
+fn binds(fixture: &str) -> Vec<String> {
+    let response = generate(fixture).unwrap();
+    let queries = response
+        .files
+        .iter()
+        .find(|file| file.name == "queries.rs")
+        .unwrap();
+    let source = String::from_utf8(queries.contents.clone()).unwrap();
+
+    // Every query method is generated on both `Database` and `DatabaseTx`,
+    // so only look at the former
+    source
+        .lines()
+        .skip_while(|line| *line != "impl Database {")
+        .take_while(|line| *line != "}")
+        .map(str::trim)
+        .filter(|line| line.starts_with(".bind("))
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn binds_out_of_order_params_by_number() {
+    assert_eq!(binds("out_of_order.json"), [".bind(id)", ".bind(name)"]);
+}
+
+#[test]
+fn binds_out_of_order_params_struct_by_number() {
+    assert_eq!(
+        binds("out_of_order_params_struct.json"),
+        [".bind(params.id)", ".bind(params.name)"]
+    );
+}
+
+#[test]
+fn binds_repeated_placeholder_once() {
+    assert_eq!(binds("repeated_placeholder.json"), [".bind(id)"]);
+}
+
+#[test]
+fn rejects_gaps() {
+    let err = generate("gap.json").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Query ListRange: parameter $2 is missing, parameters must be numbered without gaps"
+    );
+}
+
+#[test]
+fn rejects_duplicates() {
+    let err = generate("duplicate.json").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Query ListRange: parameter $1 is reported more than once"
+    );
+}
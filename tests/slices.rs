@@ -0,0 +1,50 @@
+//! `sqlc.slice()` parameters combined with stream methods.
+//!
+//! The generated code for the fixture is checked in next to it and compiled
+//! as part of this test, so a signature that doesn't borrow-check fails the
+//! build rather than a user's.
+
+use sqlc_gen_rust::{generate_code, GenerateRequest};
+
+#[rustfmt::skip]
+#[allow(dead_code, unused_imports)]
+#[path = "corpus/slices/stream/models.rs"]
+mod models;
+
+#[rustfmt::skip]
+#[allow(dead_code, unused_imports)]
+#[path = "corpus/slices/stream/queries.rs"]
+mod queries;
+
+fn corpus(path: &str) -> String {
+    let path = format!("{}/tests/corpus/slices/{path}", env!("CARGO_MANIFEST_DIR"));
+    std::fs::read_to_string(&path).unwrap()
+}
+
+#[test]
+fn compiled_stream_code_is_up_to_date() {
+    let request: GenerateRequest = serde_json::from_str(&corpus("stream.json")).unwrap();
+    let response = generate_code(request).unwrap();
+
+    for name in ["models.rs", "queries.rs"] {
+        let file = response
+            .files
+            .iter()
+            .find(|file| file.name == name)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(file.contents.clone()).unwrap(),
+            corpus(&format!("stream/{name}")),
+            "tests/corpus/slices/stream/{name} is out of date"
+        );
+    }
+}
+
+#[test]
+fn stream_methods_borrow_slices_for_the_stream() {
+    let source = corpus("stream/queries.rs");
+    assert!(source.contains("pub fn list_by_ids_stream<'a>(&'a self, ids: &'a [i32])"));
+    assert!(
+        source.contains("pub fn list_named_stream<'a>(&'a mut self, name: String, ids: &'a [i32])")
+    );
+}
@@ -0,0 +1,101 @@
+//! The transaction API generated next to `Database`.
+//!
+//! The generated code for the fixture is checked in next to it and compiled
+//! as part of this test, together with callers of every transaction method,
+//! so an API change that breaks them fails the build.
+
+use sqlc_gen_rust::{generate_code, GenerateRequest};
+
+#[rustfmt::skip]
+#[allow(dead_code, unused_imports)]
+#[path = "corpus/transactions/users/models.rs"]
+mod models;
+
+#[rustfmt::skip]
+#[allow(dead_code, unused_imports)]
+#[path = "corpus/transactions/users/queries.rs"]
+mod queries;
+
+use queries::{Database, IsolationLevel, RenameUserParams, TransactionOptions};
+use sqlx::Error as SqlxError;
+
+fn corpus(path: &str) -> String {
+    let path = format!(
+        "{}/tests/corpus/transactions/{path}",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    std::fs::read_to_string(&path).unwrap()
+}
+
+#[test]
+fn compiled_transaction_code_is_up_to_date() {
+    let request: GenerateRequest = serde_json::from_str(&corpus("users.json")).unwrap();
+    let response = generate_code(request).unwrap();
+
+    for name in ["models.rs", "queries.rs"] {
+        let file = response
+            .files
+            .iter()
+            .find(|file| file.name == name)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(file.contents.clone()).unwrap(),
+            corpus(&format!("users/{name}")),
+            "tests/corpus/transactions/users/{name} is out of date"
+        );
+    }
+}
+
+/// Only type-checked, it needs a database to run.
+#[allow(dead_code)]
+async fn uses_transactions(db: &Database) -> Result<(), SqlxError> {
+    let mut tx = db.begin().await?;
+    tx.rename_user(RenameUserParams {
+        id: 1,
+        name: "a".to_string(),
+    })
+    .await?;
+    tx.commit().await?;
+
+    for isolation in [
+        IsolationLevel::ReadCommitted,
+        IsolationLevel::RepeatableRead,
+        IsolationLevel::Serializable,
+    ] {
+        let mut tx = db.begin_with(isolation).await?;
+        let _: Vec<queries::ListUsersRow> = tx.list_users().await?;
+        tx.rollback().await?;
+    }
+
+    let name = db
+        .transaction(async |tx| {
+            let user = tx.get_user(1).await?;
+            tx.rename_user(RenameUserParams {
+                id: user.id,
+                name: format!("{}!", user.name),
+            })
+            .await?;
+            Ok(user.name)
+        })
+        .await?;
+
+    let options = TransactionOptions {
+        isolation: Some(IsolationLevel::Serializable),
+        max_retries: 5,
+    };
+    db.transaction_with(options, async |tx| {
+        let params = RenameUserParams {
+            id: 1,
+            name: name.clone(),
+        };
+        tx.rename_user(params).await
+    })
+    .await
+}
+
+#[test]
+fn transaction_options_default_to_three_retries() {
+    let options = TransactionOptions::default();
+    assert_eq!(options.isolation, None);
+    assert_eq!(options.max_retries, 3);
+}